@@ -1,161 +1,51 @@
-use std::char::MAX;
-
-use assets_manager::{asset::Png, AssetCache};
+use assets_manager::AssetCache;
+use engine::ecs::{Animator, Body, Collider, Controller, Destination, Sprite};
+use engine::geom::*;
+use engine::level::{world_to_start, EntityType, Level, TileFlags};
+use engine::procgen::{GapGenerator, ObstacleKind};
+use engine::{
+    systems, Animation, AnimationState, Campaign, Entity, GameScenes, MessageScene, Runner, Scene,
+    Tags, Tilesheet, Transition, World, TILE_SZ,
+};
 use frenderer::{
     input::{Input, Key},
     sprites::{Camera2D, SheetRegion, Transform},
-    wgpu, Renderer,
+    Renderer,
 };
-mod geom;
-mod grid;
-use geom::*;
-mod level;
-use level::{EntityType, Level};
-use rand::Rng;
-
-use rodio::{source::Source, Decoder, OutputStream};
-use std::fs::File;
-use std::io::BufReader;
 
 const GRAV_ACC: f32 = 300.0;
 const WALK_ACC: f32 = 180.0;
 const MAX_SPEED: f32 = 90.0;
-const BRAKE_DAMP: f32 = 0.9;
 const JUMP_VEL: f32 = 140.0;
 const JUMP_TIME_MAX: f32 = 0.0;
-const ATTACK_MAX_TIME: f32 = 0.6;
-const ATTACK_COOLDOWN_TIME: f32 = 0.1;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-enum Dir {
-    E,
-    W,
+struct Game {
+    scenes: GameScenes<Flight>,
+    tileset: Tilesheet,
 }
 
-impl Dir {
-    fn to_vec2(self) -> Vec2 {
-        Vec2 {
-            x: match self {
-                Dir::E => 1.0,
-                Dir::W => -1.0,
-            },
-            y: 0.0,
-        }
-    }
-}
-struct Game {
+// The title, pause and win screens draw into this sprite group
+const UI_GROUP: usize = 1;
+const UI_CAMERA: Camera2D = Camera2D {
+    screen_pos: [0.0, 0.0],
+    screen_size: [W as f32, H as f32],
+};
+
+struct Flight {
     current_level: usize,
-    levels: Vec<Level>,
-    player: Player,
-    enemies: Vec<Enemy>,
-    doors: Vec<(String, (u16, u16), Vec2)>,
+    campaign: Campaign,
+    world: World,
+    player: Entity,
+    touching_obstacle: bool,
+    // reaching the last column of obstacles wins the game
+    end_x: f32,
+    // the first level's columns are generated from this
+    seed: u64,
+    won: bool,
     camera: Camera2D,
     animations: Vec<Animation>,
 }
 
-
-struct Enemy {
-    pos: Vec2,
-    dir: Dir,
-    vel: Vec2,
-    dead: bool,
-    change_dir_timer: f32,
-    anim: AnimationState,
-}
-impl Enemy {
-    fn die(&mut self) {
-        self.dead = true;
-    }
-    fn rect(&self) -> Rect {
-        if self.dead {
-            return Rect::ZERO;
-        }
-        Rect {
-            x: self.pos.x - 18.0 + 6.0,
-            y: self.pos.y - 8.0,
-            w: 24,
-            h: 8,
-        }
-    }
-    fn trf(&self) -> Transform {
-        if self.dead {
-            return Transform::ZERO;
-        }
-        Transform {
-            w: 36,
-            h: 16,
-            x: self.pos.x,
-            y: self.pos.y,
-            rot: 0.0,
-        }
-    }
-}
-
-struct Contact {
-    a_index: usize,
-    a_rect: Rect,
-    b_index: usize,
-    b_rect: Rect,
-    displacement: Vec2,
-}
-
-struct Player {
-    pos: Vec2,
-    vel: Vec2,
-    dir: Dir,
-    touching_door: bool,
-    anim: AnimationState,
-    jumping: bool,
-    jump_timer: f32,
-    grounded: bool,
-}
-impl Player {
-    fn rect(&self) -> Rect {
-        Rect {
-            x: self.pos.x
-                + match self.dir {
-                    Dir::E => 8.0,
-                    Dir::W => 12.0,
-                },
-            y: self.pos.y - 12.0,
-            w: 16,
-            h: 24,
-        }
-    }
-    fn trf(&self) -> Transform {
-        Transform {
-            w: 36,
-            h: 36,
-            x: self.pos.x + (self.dir.to_vec2().x * 8.0) + 18.0,
-            y: self.pos.y - 12.0 + 18.0,
-            rot: 0.0,
-        }
-    }
-    // fn attack_rect(&self) -> Rect {
-    //     Rect {
-    //         x: self.pos.x
-    //             + match self.dir {
-    //                 Dir::E => 16.0,
-    //                 Dir::W => 0.0,
-    //             },
-    //         y: self.pos.y - 12.0,
-    //         w: if self.attack_timer > ATTACK_COOLDOWN_TIME {
-    //             18
-    //         } else {
-    //             0
-    //         },
-    //         h: if self.attack_timer > ATTACK_COOLDOWN_TIME {
-    //             24
-    //         } else {
-    //             0
-    //         },
-    //     }
-    // }
-}
-
-mod animation;
-use animation::Animation;
-
 #[allow(unused)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
@@ -174,184 +64,184 @@ enum AnimationKey {
     EnemyRightWalk,
     EnemyLeftWalk,
 }
-struct AnimationState {
-    animation: AnimationKey,
-    t: f32,
-}
-#[allow(unused)]
-impl AnimationState {
-    fn finished(&self, anims: &[Animation]) -> bool {
-        anims[self.animation as usize].sample(self.t).is_none()
-    }
-    fn sample(&self, anims: &[Animation]) -> SheetRegion {
-        anims[self.animation as usize]
-            .sample(self.t)
-            .unwrap_or_else(|| anims[self.animation as usize].sample(0.0).unwrap())
-    }
-    fn tick(&mut self, dt: f32) {
-        self.t += dt;
-    }
-    fn play(&mut self, anim: AnimationKey, retrigger: bool) {
-        if anim == self.animation && !retrigger {
-            return;
-        }
-        self.animation = anim;
-        self.t = 0.0;
-    }
-}
-// Feel free to change this if you use a different tilesheet
-const TILE_SZ: usize = 16;
 const W: usize = 16 * TILE_SZ;
 const H: usize = 12 * TILE_SZ;
 const SCREEN_FAST_MARGIN: f32 = 64.0;
 
 fn main() {
- 
     #[cfg(not(target_arch = "wasm32"))]
     let source =
-        assets_manager::source::FileSystem::new("/Users/abraham/Desktop/gamenginepro/3s-a-crowd/FlailySnail/content").expect("Couldn't load resources");
+        assets_manager::source::FileSystem::new("content").expect("Couldn't load resources");
     #[cfg(target_arch = "wasm32")]
     let source = assets_manager::source::Embedded::from(assets_manager::source::embed!("content"));
     let cache = assets_manager::AssetCache::with_source(source);
 
-    let drv = frenderer::Driver::new(
-        winit::window::WindowBuilder::new()
-            .with_title("test")
-            .with_inner_size(winit::dpi::LogicalSize::new(1024.0, 768.0)),
-        Some((W as u32, H as u32)),
-    );
-
-    const DT: f32 = 1.0 / 60.0;
-    let mut input = Input::default();
-    
+    Runner::new("Flaily Snail", W, H).run::<Game>(cache);
+}
 
-    let mut now = frenderer::clock::Instant::now();
-    let mut acc = 0.0;
-    drv.run_event_loop::<(), _>(
-        move |window, mut frend| {
-            let game = Game::new(&mut frend, &cache);
+// `--seed SEED` flies the same columns again.  Without one we pick a seed and
+// print it.
+fn seed() -> u64 {
+    let seed = std::env::args()
+        .skip_while(|arg| arg != "--seed")
+        .nth(1)
+        .and_then(|seed| seed.parse().ok())
+        .unwrap_or_else(rand::random);
+    println!("Seed {seed}");
+    seed
+}
 
-            (window, game, frend)
-        },
-        move |event, target, (window, ref mut game, ref mut frend)| {
-            use winit::event::{Event, WindowEvent};
-            match event {
-                Event::WindowEvent {
-                    event: WindowEvent::CloseRequested,
-                    ..
-                } => {
-                    target.exit();
-                }
-                Event::WindowEvent {
-                    event: WindowEvent::Resized(size),
-                    ..
-                } => {
-                    if !frend.gpu.is_web() {
-                        frend.resize_surface(size.width, size.height);
-                    }
-                    window.request_redraw();
-                }
-                Event::WindowEvent {
-                    event: WindowEvent::RedrawRequested,
-                    ..
-                } => {
-                    let elapsed = now.elapsed().as_secs_f32();
-                    // You can add the time snapping/death spiral prevention stuff here if you want.
-                    // I'm not using it here to keep the starter code small.
-                    acc += elapsed;
-                    now = std::time::Instant::now();
-                    // While we have time to spend
-                    while acc >= DT {
-                        // simulate a frame
-                        acc -= DT;
-                        game.simulate(&input, DT);
-                        input.next_frame();
-                    }
-                    game.render(frend);
-                    frend.render();
-                    window.request_redraw();
-                    
-                }
-                event => {
-                    input.process_input_event(&event);
-                }
-            }
-        },
-    )
-    .expect("event loop error");
+// Fills `level` with columns to fly between, up to the finish line (its
+// last column of obstacles).  Touching one sends the snail back to the
+// level's start.
+fn add_columns(level: &mut Level, seed: u64) {
+    let h = level.height() as u16;
+    let finish = level
+        .starts()
+        .iter()
+        .filter(|(t, _, _)| matches!(t, EntityType::Obstacle(..)))
+        .map(|(_, pos, _)| world_to_start(*pos, h).0)
+        .max()
+        .unwrap_or(level.width() as u16);
+    let (to_x, to_y) = level
+        .starts()
+        .iter()
+        .find(|(t, _, _)| *t == EntityType::Player)
+        .map(|(_, pos, _)| world_to_start(*pos, h))
+        .expect("Start level doesn't put the player anywhere");
+    let column = ObstacleKind::new(level.name(), to_x, to_y).with_tile("ws");
+    GapGenerator::new(column)
+        .with_gap(6, 2)
+        .with_spacing(4, 9)
+        .with_start(15)
+        .with_end(finish)
+        .generate(level, seed);
+}
 
+// Adds the entity for one level start to the world.  Enemies aren't in the
+// game for now, so their starts don't spawn anything.
+fn spawn(world: &mut World, etype: &EntityType, pos: Vec2) -> Option<Entity> {
+    match etype {
+        EntityType::Player => {
+            let e = world.spawn(pos);
+            world.velocities.insert(e, Vec2 { x: 0.0, y: 0.0 });
+            world.colliders.insert(
+                e,
+                Collider {
+                    offset: Vec2 { x: 8.0, y: -12.0 },
+                    w: 16,
+                    h: 24,
+                },
+            );
+            world.bodies.insert(
+                e,
+                Body {
+                    gravity: 1.0,
+                    grounded: true,
+                    touching: TileFlags::OPEN,
+                },
+            );
+            world.sprites.insert(
+                e,
+                Sprite {
+                    anim: AnimationState::new(AnimationKey::PlayerRightIdle as usize),
+                    offset: Vec2 { x: 26.0, y: 6.0 },
+                    w: 36,
+                    h: 36,
+                },
+            );
+            world.animators.insert(
+                e,
+                Animator {
+                    idle: AnimationKey::PlayerRightIdle as usize,
+                    walk: AnimationKey::PlayerRightWalk as usize,
+                    jump_rise: AnimationKey::PlayerRightJumpRise as usize,
+                    jump_fall: AnimationKey::PlayerRightJumpFall as usize,
+                },
+            );
+            // flap whenever the key is down, even in midair
+            world.controllers.insert(
+                e,
+                Controller {
+                    air_jumps: true,
+                    run_acc: WALK_ACC,
+                    max_speed: MAX_SPEED,
+                    ..Controller::new(Key::ArrowUp, JUMP_VEL, JUMP_TIME_MAX)
+                },
+            );
+            world.tags.insert(e, Tags::PLAYER);
+            Some(e)
+        }
+        EntityType::Obstacle(to, x, y) => {
+            let e = world.spawn(pos);
+            world.colliders.insert(
+                e,
+                Collider {
+                    offset: Vec2 { x: -8.0, y: -8.0 },
+                    w: 16,
+                    h: 16,
+                },
+            );
+            world.destinations.insert(
+                e,
+                Destination {
+                    level: to.clone(),
+                    x: *x,
+                    y: *y,
+                },
+            );
+            world.tags.insert(e, Tags::OBSTACLE);
+            Some(e)
+        }
+        EntityType::Enemy | EntityType::Mud => None,
+    }
 }
 
-impl Game {
-    fn new(renderer: &mut Renderer, cache: &AssetCache) -> Self {
-        let tile_handle = cache
-            .load::<Png>("tileset")
-            .expect("Couldn't load tilesheet img");
-        let tile_img = tile_handle.read().0.to_rgba8();
-        let tile_tex = renderer.create_array_texture(
-            &[&tile_img],
-            wgpu::TextureFormat::Rgba8UnormSrgb,
-            tile_img.dimensions(),
-            Some("tiles-sprites"),
+impl engine::Game for Game {
+    fn init(renderer: &mut Renderer, cache: &AssetCache) -> Self {
+        let tileset = Tilesheet::load(renderer, cache, "tileset");
+        // the level's group is resized to fit when we render it
+        renderer.sprite_group_add(
+            tileset.texture(),
+            vec![Transform::ZERO; 1],
+            vec![SheetRegion::ZERO; 1],
+            UI_CAMERA,
         );
-        let levels = vec![
-            Level::from_str(
-                &cache
-                    .load::<String>("level1")
-                    .expect("Couldn't access level1.txt")
-                    .read(),
-            ),
-            Level::from_str(
-                &cache
-                    .load::<String>("house")
-                    .expect("Couldn't access house.txt")
-                    .read(),
-            ),
-            Level::from_str(
-                &cache
-                    .load::<String>("shop")
-                    .expect("Couldn't access shop.txt")
-                    .read(),
-            ),
-        ];
-        let current_level = 0;
+        renderer.sprite_group_add(
+            tileset.texture(),
+            vec![Transform::ZERO; 1],
+            vec![SheetRegion::ZERO; 1],
+            UI_CAMERA,
+        );
+        let mut campaign = match Campaign::load(cache, "campaign") {
+            Ok(campaign) => campaign,
+            Err(err) => {
+                return Game {
+                    scenes: GameScenes::failed(UI_GROUP, UI_CAMERA, &err),
+                    tileset,
+                }
+            }
+        };
+        let current_level = campaign.start();
+        let seed = seed();
+        add_columns(campaign.level_mut(current_level), seed);
         let camera = Camera2D {
             screen_pos: [0.0, 0.0],
             screen_size: [W as f32, H as f32],
         };
-        let sprite_estimate =
-            levels[current_level].sprite_count() + levels[current_level].starts().len();
-        renderer.sprite_group_add(
-            &tile_tex,
-            vec![Transform::ZERO; sprite_estimate],
-            vec![SheetRegion::ZERO; sprite_estimate],
-            camera,
-        );
-        let player_start = levels[current_level]
-            .starts()
-            .iter()
-            .find(|(t, _)| *t == EntityType::Player)
-            .map(|(_, ploc)| *ploc + Vec2 { x: 0.0, y: 200.0 })
-            .expect("Start level doesn't put the player anywhere");
-        let mut game = Game {
+        let player_start = player_start(campaign.level(current_level));
+        let mut world = World::default();
+        let player = spawn(&mut world, &EntityType::Player, player_start).unwrap();
+        let mut flight = Flight {
             current_level,
             camera,
-            levels,
-            enemies: vec![],
-            doors: vec![],
-            player: Player {
-                vel: Vec2 { x: 0.0, y: 0.0 },
-                pos: player_start,
-                dir: Dir::E,
-                anim: AnimationState {
-                    animation: AnimationKey::PlayerRightIdle,
-                    t: 0.0,
-                },
-                touching_door: false,
-                jumping: false,
-                jump_timer: 0.0,
-                grounded: true,
-            },
+            campaign,
+            world,
+            player,
+            touching_obstacle: false,
+            end_x: f32::INFINITY,
+            seed,
+            won: false,
             animations: vec![
                 Animation::with_frame(SheetRegion::ZERO),
                 Animation::with_frames(
@@ -470,291 +360,138 @@ impl Game {
                 .flip_horizontal(),
             ],
         };
-        game.enter_level(player_start);
-
-        game
-
-    }
-    fn level(&self) -> &Level {
-        &self.levels[self.current_level]
-    }
-    fn enter_level(&mut self, player_pos: Vec2) {
-        self.doors.clear();
-        self.enemies.clear();
-        // we will probably enter at a door
-        self.player.touching_door = true;
-        self.player.pos = player_pos;
-        for (etype, pos) in self.levels[self.current_level].starts().iter() {
-            match etype {
-                EntityType::Player => {}
-                EntityType::Door(rm, x, y) => self.doors.push((rm.clone(), (*x, *y), *pos)),
-                EntityType::Enemy => self.enemies.push(Enemy {
-                    dead: false,
-                    pos: *pos,
-                    vel: Vec2 { x: 0.0, y: 0.0 },
-                    dir: if rand::thread_rng().gen_bool(0.5) {
-                        Dir::E
-                    } else {
-                        Dir::W
-                    },
-                    anim: AnimationState {
-                        animation: AnimationKey::EnemyLeftWalk,
-                        t: 0.0,
-                    },
-                    change_dir_timer: rand::thread_rng().gen_range(3.0..5.0),
-                }),
-            }
+        flight.enter_level(player_start);
+        Game {
+            scenes: GameScenes::new(
+                MessageScene::title(UI_GROUP, UI_CAMERA, "Flaily Snail", take_off),
+                flight,
+            ),
+            tileset,
         }
     }
-    fn sprite_count(&self) -> usize {
-        //todo!("count how many entities and other sprites we have");
-        self.level().sprite_count() + self.enemies.len() + 1
-    }
     fn render(&mut self, frend: &mut Renderer) {
-        // make this exactly as big as we need
-        frend.sprite_group_resize(0, self.sprite_count());
-        frend.sprite_group_set_camera(0, self.camera);
-
-        let sprites_used = self.level().render_into(frend, 0);
-        let (sprite_posns, sprite_gfx) = frend.sprites_mut(0, sprites_used..);
-
-        // commented out to get rid of enemy for now
-        // for (enemy, (trf, uv)) in self
-        //     .enemies
-        //     .iter()
-        //     .zip(sprite_posns.iter_mut().zip(sprite_gfx.iter_mut()))
-        // {
-        //     *trf = enemy.trf();
-        //     *uv = self.animations[AnimationKey::EnemyLeftWalk as usize]
-        //         .sample(0.0)
-        //         .unwrap();
-        // }
-        let sprite_posns = &mut sprite_posns[self.enemies.len()..];
-        let sprite_gfx = &mut sprite_gfx[self.enemies.len()..];
-        sprite_posns[0] = self.player.trf();
-        sprite_gfx[0] = self.player.anim.sample(&self.animations);
-
-        frend.sprite_group_set_camera(
-            0,
-            Camera2D {
-                screen_pos: [(self.player.pos.x - (W / 4) as f32), 7_f32],
-                screen_size: [W as f32, H as f32],
-            },
-        );
+        self.scenes.render(frend);
     }
     fn simulate(&mut self, input: &Input, dt: f32) {
-        let acc = Vec2 {
-            // x: WALK_ACC * input.key_axis(Key::ArrowLeft, Key::ArrowRight),
-            x: WALK_ACC,
-            y: GRAV_ACC,
+        self.scenes.simulate(input, dt);
+    }
+    fn hot_reload(&mut self, renderer: &mut Renderer, cache: &AssetCache) {
+        self.tileset.reload(renderer, cache);
+        let GameScenes::Loaded { state: flight, .. } = &mut self.scenes else {
+            return;
         };
-
-        if input.is_key_down(Key::ArrowRight) {
-            self.player.dir = Dir::E;
-            self.player.anim.play(AnimationKey::PlayerRightWalk, false);
-        } else if input.is_key_down(Key::ArrowLeft) {
-            self.player.dir = Dir::W;
-            self.player.anim.play(AnimationKey::PlayerLeftWalk, false);
-        }
-
-        // Handle jumping NOT WORKING
-        if input.is_key_down(Key::ArrowUp) {
-            self.player.jumping = true;
-            self.player.jump_timer = JUMP_TIME_MAX;
-            self.player.vel.y = JUMP_VEL;
-        }
-
-        if self.player.jumping {
-            self.player.jump_timer -= dt;
-            self.player.jumping = self.player.jump_timer > 0.0;
-        }
-
-        self.player.vel.y -= GRAV_ACC * dt;
-        self.player.vel.x += acc.x * dt;
-        if acc.x.abs() < 0.1 {
-            self.player.vel.x *= BRAKE_DAMP;
-        }
-        self.player.vel.x = self.player.vel.x.clamp(-MAX_SPEED, MAX_SPEED);
-
-        self.player.pos += self.player.vel * dt;
-
-        let lw = self.level().width();
-        let lh = self.level().height();
-
-        self.player.pos.x = self.player.pos.x.clamp(
-            0.0,
-            lw as f32 * TILE_SZ as f32 - self.player.rect().w as f32 / 2.0,
-        );
-        self.player.pos.y = self
-            .player
-            .pos
-            .y
-            .clamp(0.0, H as f32 - self.player.rect().h as f32);
-
-        // if player's y position is less than or equal to 0, then they are grounded
-        // so, set grounded to True. If their y position is greater than 0, then they
-        // are not grounded, so set grounded to False.
-        self.player.grounded = self.player.pos.y <= 0.0;
-        if self.player.grounded {
-            // if the player is grounded, set their y velocity to 0
-            self.player.vel.y = 0.0;
-        }
-
-        // if the player is jumping
-        if self.player.jumping {
-            if self.player.vel.y > 0.0 {
-                self.player
-                    .anim
-                    .play(AnimationKey::PlayerRightJumpRise, false);
-            } else {
-                self.player
-                    .anim
-                    .play(AnimationKey::PlayerRightJumpFall, false);
-            }
-        } else {
-            match self.player.dir {
-                Dir::E => self.player.anim.play(AnimationKey::PlayerRightWalk, false),
-                Dir::W => self.player.anim.play(AnimationKey::PlayerLeftWalk, false),
-            }
-        }
-
-        self.player.anim.tick(dt);
-
-        // Enemy calculations
-        for enemy in self.enemies.iter_mut() {
-            if enemy.dead {
-                continue;
-            }
-            enemy.change_dir_timer -= dt;
-            if enemy.change_dir_timer <= 0.0 {
-                enemy.dir = if enemy.dir == Dir::E { Dir::W } else { Dir::E };
-                enemy.change_dir_timer = rand::thread_rng().gen_range(3.0..5.0);
-            }
-
-            enemy.vel.x = MAX_SPEED
-                * match enemy.dir {
-                    Dir::E => 1.0,
-                    Dir::W => -1.0,
-                };
-            enemy.pos += enemy.vel * dt;
-            enemy.pos.x = enemy.pos.x.clamp(
-                0.0,
-                lw as f32 * TILE_SZ as f32 - enemy.rect().w as f32 / 2.0,
-            );
-            enemy.pos.y = enemy.pos.y.clamp(
-                0.0,
-                lh as f32 * TILE_SZ as f32 * H as f32 - enemy.rect().h as f32 / 2.0,
-            );
-            match enemy.dir {
-                Dir::E => {
-                    enemy.anim.play(AnimationKey::EnemyRightWalk, false);
+        match flight.campaign.reload(cache) {
+            Ok(changed) => {
+                // a reloaded start level needs its columns again
+                let start = flight.campaign.start();
+                if changed.contains(&start) {
+                    add_columns(flight.campaign.level_mut(start), flight.seed);
                 }
-                Dir::W => {
-                    enemy.anim.play(AnimationKey::EnemyLeftWalk, false);
+                if changed.contains(&flight.current_level) {
+                    flight.enter_level(flight.player_pos());
                 }
             }
-            enemy.anim.tick(dt);
+            Err(err) => eprintln!("{err}"),
         }
+    }
+}
 
-        // Door collision and response
-        let mut triggers = vec![];
-        let mut contacts = vec![];
-        let prect = self.player.rect();
-
-        let enemy_rects = self
-            .enemies
-            .iter()
-            .map(|enemy| Rect {
-                x: enemy.pos.x,
-                y: enemy.pos.y,
-                w: 16,
-                h: 16,
-            })
-            .collect::<Vec<_>>();
+struct Flying;
 
-        let mut player_tile_contacts = vec![];
-        let mut enemy_tile_contacts = vec![];
+fn take_off(flight: &mut Flight) -> Box<dyn Scene<Flight>> {
+    flight.restart();
+    Box::new(Flying)
+}
 
-        self::Game::gather_contacts_tiles(&enemy_rects, self.level(), &mut enemy_tile_contacts);
-        self::Game::gather_contacts_tiles(&[prect], self.level(), &mut player_tile_contacts);
+impl Scene<Flight> for Flying {
+    fn simulate(&mut self, flight: &mut Flight, input: &Input, dt: f32) -> Transition<Flight> {
+        if input.is_key_pressed(Key::Escape) {
+            return Transition::Push(Box::new(MessageScene::pause(UI_GROUP, UI_CAMERA)));
+        }
+        flight.simulate(input, dt);
+        if flight.won {
+            Transition::Replace(Box::new(MessageScene::win(UI_GROUP, UI_CAMERA, take_off)))
+        } else {
+            Transition::None
+        }
+    }
+    fn render(&mut self, flight: &mut Flight, frend: &mut Renderer) {
+        flight.render(frend);
+    }
+}
 
-        self::Game::gather_contacts(&[prect], &enemy_rects, &mut contacts);
+fn player_start(level: &Level) -> Vec2 {
+    level
+        .starts()
+        .iter()
+        .find(|(t, _, _)| *t == EntityType::Player)
+        .map(|(_, ploc, _)| *ploc + Vec2 { x: 0.0, y: 200.0 })
+        .expect("Start level doesn't put the player anywhere")
+}
 
-        self.player.grounded = false;
-        for contact in player_tile_contacts {
-            let disp = Self::compute_disp(self.player.rect(), contact.b_rect);
-            self.player.pos += disp;
-            if disp.y > 0.0 {
-                self.player.grounded = true;
-                self.player.vel.y = 0.0;
-            }
-        }
+impl Flight {
+    fn render(&mut self, frend: &mut Renderer) {
+        let camera = Camera2D {
+            screen_pos: [(self.player_pos().x - (W / 4) as f32), 7_f32],
+            screen_size: [W as f32, H as f32],
+        };
+        // make this exactly as big as we need
+        frend.sprite_group_resize(0, self.sprite_count(&camera));
+        frend.sprite_group_set_camera(0, camera);
 
-        for contact in enemy_tile_contacts {
-            let disp = Self::compute_disp(contact.a_rect, contact.b_rect);
-            self.enemies[contact.a_index].pos += disp;
-        }
+        let sprites_used = self.level().render_visible_into(frend, 0, &camera);
+        self.world
+            .render_into(frend, 0, sprites_used, &self.animations);
+    }
+    fn simulate(&mut self, input: &Input, dt: f32) {
+        let lw = self.level().width();
+        let lh = self.level().height();
 
-        let door_rects = self
-            .doors
-            .iter()
-            .map(|(_, _, pos)| Rect {
-                x: pos.x - 8.0,
-                y: pos.y - 8.0,
-                w: 16,
-                h: 16,
-            })
-            .collect::<Vec<_>>();
-        self::Game::gather_contacts(&[prect], &door_rects, &mut triggers);
+        systems::controls(&mut self.world, input, dt);
+        systems::patrol(&mut self.world, dt);
+        systems::gravity(&mut self.world, GRAV_ACC, dt);
+        systems::movement(&mut self.world, self.campaign.level(self.current_level), dt);
+        systems::keep_in_bounds(&mut self.world, (lw * TILE_SZ) as f32, H as f32);
+        systems::tile_collision(&mut self.world, self.campaign.level(self.current_level));
+        self.world.update_broadphase();
+        systems::animation(&mut self.world, dt);
+        self.campaign.level_mut(self.current_level).tick(dt);
+
+        // Obstacle collision and response
+        let triggers = self.world.contacts(Tags::PLAYER, Tags::OBSTACLE);
         if triggers.is_empty() {
-            self.player.touching_door = false;
+            self.touching_obstacle = false;
         }
-        for Contact { b_index: door, .. } in triggers.drain(..) {
-            // enter door if player has moved, wasn't previously touching door
-            if !self.player.touching_door {
-                self.player.touching_door = true;
-                let (door_to, door_to_pos, _door_pos) = &self.doors[door];
-                let dest = self
-                    .levels
-                    .iter()
-                    .position(|l| l.name() == door_to)
-                    .expect("door to invalid room {door_to}!");
-                if dest == self.current_level {
-                    self.player.pos = self
-                        .level()
-                        .grid_to_world((door_to_pos.0 as usize, door_to_pos.1 as usize))
-                        + Vec2 {
-                            x: TILE_SZ as f32 / 2.0,
-                            y: -12.0 + TILE_SZ as f32 / 2.0,
-                        };
-                } else {
-                    self.current_level = dest;
-                    self.enter_level(
-                        self.level()
-                            .grid_to_world((door_to_pos.0 as usize, door_to_pos.1 as usize))
-                            + Vec2 {
-                                x: 0.0 + TILE_SZ as f32 / 2.0,
-                                y: -12.0 + TILE_SZ as f32 / 2.0,
-                            },
-                    );
-                }
-                break;
+        // enter obstacle if player has moved, wasn't previously touching obstacle
+        if let Some(&(_, obstacle)) = triggers.first() {
+            if !self.touching_obstacle {
+                self.touching_obstacle = true;
+                self.hit_obstacle(obstacle);
             }
         }
-        while self.player.pos.x
+        // Goal tiles finish the level, hazard tiles send the snail back to the start
+        let touching = self.world.bodies.get(self.player).unwrap().touching;
+        if touching.goal {
+            self.finish_level();
+        } else if touching.hazard {
+            *self.world.positions.get_mut(self.player).unwrap() = player_start(self.level());
+        }
+
+        let player_pos = self.player_pos();
+        while player_pos.x
             > self.camera.screen_pos[0] + self.camera.screen_size[0] - SCREEN_FAST_MARGIN
         {
             self.camera.screen_pos[0] += 1.0;
         }
-        while self.player.pos.x < self.camera.screen_pos[0] + SCREEN_FAST_MARGIN {
+        while player_pos.x < self.camera.screen_pos[0] + SCREEN_FAST_MARGIN {
             self.camera.screen_pos[0] -= 1.0;
         }
-        while self.player.pos.y
+        while player_pos.y
             > self.camera.screen_pos[1] + self.camera.screen_size[1] - SCREEN_FAST_MARGIN
         {
             self.camera.screen_pos[1] += 1.0;
         }
-        while self.player.pos.y < self.camera.screen_pos[1] + SCREEN_FAST_MARGIN {
+        while player_pos.y < self.camera.screen_pos[1] + SCREEN_FAST_MARGIN {
             self.camera.screen_pos[1] -= 1.0;
         }
         self.camera.screen_pos[0] =
@@ -762,79 +499,94 @@ impl Game {
         self.camera.screen_pos[1] =
             self.camera.screen_pos[1].clamp(0.0, (lh * TILE_SZ).max(H) as f32 - H as f32);
     }
-    fn gather_contacts_tiles(rects: &[Rect], level: &Level, contacts: &mut Vec<Contact>) {
-        for (rect_i, rect) in rects.iter().enumerate() {
-            for (tr, _td) in level.tiles_within(*rect).filter(|(_, td)| td.solid) {
-                if let Some(displacement) = rect.overlap(tr) {
-                    contacts.push(Contact {
-                        a_index: rect_i,
-                        a_rect: *rect,
-                        b_index: 0,
-                        b_rect: tr,
-                        displacement,
-                    });
+    // Starts over from the first level with a fresh snail
+    fn restart(&mut self) {
+        self.current_level = self.campaign.start();
+        let player_start = player_start(self.level());
+        self.world.clear();
+        self.player = spawn(&mut self.world, &EntityType::Player, player_start).unwrap();
+        self.camera.screen_pos = [0.0, 0.0];
+        self.won = false;
+        self.enter_level(player_start);
+    }
+    fn level(&self) -> &Level {
+        self.campaign.level(self.current_level)
+    }
+    fn player_pos(&self) -> Vec2 {
+        *self.world.positions.get(self.player).unwrap()
+    }
+    fn enter_level(&mut self, player_pos: Vec2) {
+        // the player carries over between levels, everything else is replaced
+        let old: Vec<Entity> = self
+            .world
+            .entities()
+            .filter(|e| *e != self.player)
+            .collect();
+        for e in old {
+            self.world.despawn(e);
+        }
+        // we will probably enter at a obstacle
+        self.touching_obstacle = true;
+        *self.world.positions.get_mut(self.player).unwrap() = player_pos;
+        for (etype, pos, props) in self.campaign.level(self.current_level).starts().iter() {
+            if *etype != EntityType::Player {
+                if let Some(e) = spawn(&mut self.world, etype, *pos) {
+                    self.world.props.insert(e, props.clone());
                 }
             }
         }
+        // the goal is the furthest column of obstacles that send you back
+        // to the start of this level (rather than through a door)
+        let name = self.level().name();
+        self.end_x = self
+            .world
+            .with_tag(Tags::OBSTACLE)
+            .filter(|e| {
+                self.world
+                    .destinations
+                    .get(*e)
+                    .is_some_and(|d| d.level == name)
+            })
+            .filter_map(|e| self.world.positions.get(e).map(|pos| pos.x))
+            .reduce(f32::max)
+            .unwrap_or(f32::INFINITY);
     }
-
-    fn gather_contacts(rects_a: &[Rect], rects_b: &[Rect], contacts: &mut Vec<Contact>) {
-        for (a_i, a) in rects_a.iter().enumerate() {
-            for (b_i, b) in rects_b.iter().enumerate() {
-                if let Some(displacement) = a.overlap(*b) {
-                    contacts.push(Contact {
-                        a_index: a_i,
-                        a_rect: *a,
-                        b_index: b_i,
-                        b_rect: *b,
-                        displacement,
-                    });
-                }
+    // Flies on to the campaign's next level, or wins after the last
+    fn finish_level(&mut self) {
+        match self.campaign.next(self.current_level) {
+            Some(next) => {
+                self.current_level = next;
+                self.enter_level(player_start(self.level()));
             }
+            None => self.won = true,
         }
     }
-
-    // computes the displacement to resolve the contact
-    fn compute_disp(a: Rect, b: Rect) -> Vec2 {
-        let mut displacement = a.overlap(b).unwrap_or(Vec2 { x: 0.0, y: 0.0 });
-        if displacement.x < displacement.y {
-            displacement.y = 0.0;
-        } else {
-            displacement.x = 0.0;
-        }
-        // rectangle a is left of rectangle b, displacement becomes negative
-        // rectabnel a is bellow rectangle b, displacement becomes negative
-        if a.x < b.x {
-            displacement.x = -displacement.x;
+    fn hit_obstacle(&mut self, obstacle: Entity) {
+        if self.world.positions.get(obstacle).unwrap().x >= self.end_x {
+            self.finish_level();
+            if !self.won {
+                return;
+            }
         }
-        if a.y < b.y {
-            displacement.y = -displacement.y;
+        let Destination { level, x, y } = self.world.destinations.get(obstacle).cloned().unwrap();
+        // only campaigns read from a manifest have their obstacles checked
+        let Some(dest) = self.campaign.index_of(&level) else {
+            eprintln!("obstacle to invalid room {level}!");
+            return;
+        };
+        let offset = Vec2 {
+            x: TILE_SZ as f32 / 2.0,
+            y: -12.0 + TILE_SZ as f32 / 2.0,
+        };
+        if dest == self.current_level {
+            *self.world.positions.get_mut(self.player).unwrap() =
+                self.level().grid_to_world((x as usize, y as usize)) + offset;
+        } else {
+            self.current_level = dest;
+            self.enter_level(self.level().grid_to_world((x as usize, y as usize)) + offset);
         }
-        displacement
     }
-
-    // collision response for the player and enemies, give the player knockback and lose one heart
-    // fn player_enemy_collision_response(&mut self, contacts: &mut [Contact]) {
-    //     for contact in contacts {
-    //         if self.knockback_timer > 0.0 {
-    //             continue;
-    //         }
-    //         self.knockback_timer = KNOCKBACK_TIME;
-    //         self.health -= 1;
-    //     }
-    // }
-
-    // collision response for the player's attack and enemies, remove the enemy
-    fn attack_enemy_collision_response(&mut self, contacts: &mut [Contact]) {
-        let mut to_remove = vec![];
-        for contact in contacts {
-            to_remove.push(contact.b_index);
-        }
-        to_remove.sort_unstable();
-        to_remove.dedup();
-        for i in to_remove.iter().rev() {
-            // USE SWAP REMOVE INTEAD OF REMOVE
-            self.enemies.remove(*i);
-        }
+    fn sprite_count(&self, camera: &Camera2D) -> usize {
+        self.level().visible_sprite_count(camera) + self.world.sprite_count()
     }
 }
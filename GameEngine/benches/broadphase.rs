@@ -0,0 +1,160 @@
+// Times finding every overlapping pair between two crowds of entities, once
+// with the every-rect-against-every-rect loop in collision::gather_contacts
+// and once with a SpatialHash rebuilt each frame the way World does it.
+// Then times the tile lookups movement and tile_collision make for those
+// crowds, with Level::tiles_within against looking each cell up on its own.
+//
+//     cargo bench --bench broadphase
+use engine::collision::{gather_contacts, SpatialHash};
+use engine::geom::{Rect, Vec2};
+use engine::level::{Level, TileData};
+use engine::TILE_SZ;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::time::{Duration, Instant};
+
+// frames to time each way
+const FRAMES: u32 = 20;
+
+// `n` sprite-sized rects scattered over a level `w` tiles wide and 15 tall
+fn crowd(rng: &mut StdRng, n: usize, w: usize) -> Vec<Rect> {
+    (0..n)
+        .map(|_| Rect {
+            x: rng.gen_range(0.0..(w * TILE_SZ) as f32),
+            y: rng.gen_range(0.0..(15 * TILE_SZ) as f32),
+            w: rng.gen_range(8..=32),
+            h: rng.gen_range(8..=24),
+        })
+        .collect()
+}
+
+// A level `w` tiles wide and 15 tall, a third of it solid
+fn level(rng: &mut StdRng, w: usize) -> Level {
+    let rows: Vec<String> = (0..15)
+        .map(|_| {
+            (0..w)
+                .map(|_| {
+                    if rng.gen_range(0..3) == 0 {
+                        "gnd"
+                    } else {
+                        "air"
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect();
+    format!(
+        "bench {w} 15\n====\nair o 0 0 16 16\ngnd s 16 0 16 16\n====\n{}\n====\n",
+        rows.join("\n")
+    )
+    .parse()
+    .unwrap()
+}
+
+// The cells tiles_within covers, each found with grid_to_world and Grid::get
+fn tiles_one_by_one(level: &Level, rect: Rect) -> Vec<(Rect, &TileData)> {
+    let (l, t) = level.world_to_grid(rect.origin());
+    let (r, b) = level.world_to_grid(Vec2 {
+        x: rect.x + rect.w as f32,
+        y: rect.y + rect.h as f32,
+    });
+    let mut tiles = vec![];
+    for layer in level.layers().iter().filter(|l| l.collision) {
+        for row in b..t + 2 {
+            for col in l..r + 2 {
+                if let Some(tile) = layer.grid.get(col, row) {
+                    let world = level.grid_to_world((col, row));
+                    let tr = Rect {
+                        x: world.x,
+                        y: world.y,
+                        w: TILE_SZ as u16,
+                        h: TILE_SZ as u16,
+                    };
+                    tiles.push((tr, level.tile_data(*tile)));
+                }
+            }
+        }
+    }
+    tiles
+}
+
+fn time(mut frame: impl FnMut() -> usize) -> (Duration, usize) {
+    let start = Instant::now();
+    let mut pairs = 0;
+    for _ in 0..FRAMES {
+        pairs = frame();
+    }
+    (start.elapsed() / FRAMES, pairs)
+}
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(25);
+    println!("  movers  statics   pairs  double loop  spatial hash");
+    for (movers, statics, w) in [
+        (10, 100, 100),
+        (100, 500, 200),
+        (500, 2000, 400),
+        (2000, 5000, 1000),
+    ] {
+        let a = crowd(&mut rng, movers, w);
+        let b = crowd(&mut rng, statics, w);
+        let mut contacts = vec![];
+        let (looped, loop_pairs) = time(|| {
+            contacts.clear();
+            gather_contacts(&a, &b, &mut contacts);
+            contacts.len()
+        });
+        let mut hash = SpatialHash::default();
+        let (hashed, hash_pairs) = time(|| {
+            hash.clear();
+            for (i, r) in b.iter().enumerate() {
+                hash.insert(i, *r);
+            }
+            a.iter().map(|r| hash.query(*r).len()).sum()
+        });
+        assert_eq!(loop_pairs, hash_pairs, "the two ways found different pairs");
+        println!(
+            "{movers:>8} {statics:>8} {loop_pairs:>7} {:>10.3}ms {:>11.3}ms",
+            looped.as_secs_f64() * 1000.0,
+            hashed.as_secs_f64() * 1000.0
+        );
+    }
+    println!();
+    println!("  movers   tiles  one by one  tiles_within");
+    for (movers, w) in [(10, 100), (100, 200), (500, 400), (2000, 1000)] {
+        let level = level(&mut rng, w);
+        let a = crowd(&mut rng, movers, w);
+        // about what three sweeps and a landing look up per body
+        let (single, single_tiles) = time(|| {
+            a.iter()
+                .map(|r| {
+                    (0..4)
+                        .map(|_| tiles_one_by_one(&level, *r).len())
+                        .sum::<usize>()
+                })
+                .sum()
+        });
+        let (within, within_tiles) = time(|| {
+            a.iter()
+                .map(|r| {
+                    (0..4)
+                        .map(|_| level.tiles_within(*r).count())
+                        .sum::<usize>()
+                })
+                .sum()
+        });
+        assert_eq!(
+            single_tiles, within_tiles,
+            "the two ways found different tiles"
+        );
+        for r in a.iter() {
+            let found: Vec<_> = level.tiles_within(*r).collect();
+            assert!(found == tiles_one_by_one(&level, *r));
+        }
+        println!(
+            "{movers:>8} {within_tiles:>7} {:>9.3}ms {:>11.3}ms",
+            single.as_secs_f64() * 1000.0,
+            within.as_secs_f64() * 1000.0
+        );
+    }
+}
@@ -0,0 +1,378 @@
+use assets_manager::{asset::Png, AssetCache};
+use engine::ecs::{Animator, Body, Collider, Controller, Destination, Sprite};
+use engine::geom::*;
+use engine::level::{EntityType, Level, TileFlags};
+use engine::procgen::{JumpArc, Terrain};
+use engine::{systems, Animation, AnimationState, Entity, Runner, Tags, World, TILE_SZ};
+use frenderer::{
+    input::{Input, Key},
+    sprites::{Camera2D, SheetRegion, Transform},
+    wgpu, Renderer,
+};
+
+const GRAV_ACC: f32 = 300.0;
+const WALK_ACC: f32 = 180.0;
+const MAX_SPEED: f32 = 90.0;
+const JUMP_VEL: f32 = 140.0;
+const JUMP_TIME_MAX: f32 = 0.25;
+
+struct Game {
+    current_level: usize,
+    levels: Vec<Level>,
+    world: World,
+    player: Entity,
+    touching_obstacle: bool,
+    camera: Camera2D,
+    animations: Vec<Animation>,
+}
+
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum AnimationKey {
+    Blank = 0,
+    PlayerRightIdle,
+    PlayerRightWalk,
+    PlayerRightJumpRise,
+    PlayerRightJumpFall,
+    PlayerRightAttack,
+    PlayerLeftIdle,
+    PlayerLeftWalk,
+    PlayerLeftJumpRise,
+    PlayerLeftJumpFall,
+    PlayerLeftAttack,
+}
+const W: usize = 16 * TILE_SZ;
+const H: usize = 12 * TILE_SZ;
+const SCREEN_FAST_MARGIN: f32 = 64.0;
+
+fn main() {
+    #[cfg(not(target_arch = "wasm32"))]
+    let source =
+        assets_manager::source::FileSystem::new("content").expect("Couldn't load resources");
+    #[cfg(target_arch = "wasm32")]
+    let source = assets_manager::source::Embedded::from(assets_manager::source::embed!("content"));
+    let cache = assets_manager::AssetCache::with_source(source);
+
+    Runner::new("platformer", W, H)
+        .with_music("content/gamemusic.ogg")
+        .run::<Game>(cache);
+}
+
+// Adds the entity for one level start to the world.  The example only has a
+// player and obstacles.
+fn spawn(world: &mut World, etype: &EntityType, pos: Vec2) -> Option<Entity> {
+    match etype {
+        EntityType::Player => {
+            let e = world.spawn(pos);
+            world.velocities.insert(e, Vec2 { x: 0.0, y: 0.0 });
+            world.colliders.insert(
+                e,
+                Collider {
+                    offset: Vec2 { x: 8.0, y: -12.0 },
+                    w: 16,
+                    h: 24,
+                },
+            );
+            world.bodies.insert(
+                e,
+                Body {
+                    gravity: 1.0,
+                    grounded: true,
+                    touching: TileFlags::OPEN,
+                },
+            );
+            world.sprites.insert(
+                e,
+                Sprite {
+                    anim: AnimationState::new(AnimationKey::PlayerRightIdle as usize),
+                    offset: Vec2 { x: 26.0, y: 6.0 },
+                    w: 36,
+                    h: 36,
+                },
+            );
+            world.animators.insert(
+                e,
+                Animator {
+                    idle: AnimationKey::PlayerRightIdle as usize,
+                    walk: AnimationKey::PlayerRightWalk as usize,
+                    jump_rise: AnimationKey::PlayerRightJumpRise as usize,
+                    jump_fall: AnimationKey::PlayerRightJumpFall as usize,
+                },
+            );
+            world.controllers.insert(
+                e,
+                Controller {
+                    run_acc: WALK_ACC,
+                    max_speed: MAX_SPEED,
+                    ..Controller::new(Key::ArrowUp, JUMP_VEL, JUMP_TIME_MAX)
+                },
+            );
+            world.tags.insert(e, Tags::PLAYER);
+            Some(e)
+        }
+        EntityType::Obstacle(to, x, y) => {
+            let e = world.spawn(pos);
+            world.colliders.insert(
+                e,
+                Collider {
+                    offset: Vec2 { x: -8.0, y: -8.0 },
+                    w: 16,
+                    h: 16,
+                },
+            );
+            world.destinations.insert(
+                e,
+                Destination {
+                    level: to.clone(),
+                    x: *x,
+                    y: *y,
+                },
+            );
+            world.tags.insert(e, Tags::OBSTACLE);
+            Some(e)
+        }
+        EntityType::Enemy | EntityType::Mud => None,
+    }
+}
+
+impl engine::Game for Game {
+    fn init(renderer: &mut Renderer, cache: &AssetCache) -> Self {
+        let tile_handle = cache
+            .load::<Png>("tileset")
+            .expect("Couldn't load tilesheet img");
+        let tile_img = tile_handle.read().0.to_rgba8();
+        let tile_tex = renderer.create_array_texture(
+            &[&tile_img],
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            tile_img.dimensions(),
+            Some("tiles-sprites"),
+        );
+        // the example has no scenes to show a broken level in, so just say what's wrong
+        let mut levels = vec![Level::load(cache, "level1").unwrap_or_else(|e| panic!("{e}"))];
+        // roll the run-up to the first sign into hills the player can jump up
+        let hills = Terrain::new("gdfc", "gdc", "air")
+            .with_jump(JumpArc::new(JUMP_VEL, GRAV_ACC, MAX_SPEED, 16.0, 24.0))
+            .with_start(8)
+            .with_end(21);
+        if let Err(err) = hills.generate(&mut levels[0], 1) {
+            eprintln!("{err}, so the run-up stays flat");
+        }
+        let current_level = 0;
+        let camera = Camera2D {
+            screen_pos: [0.0, 0.0],
+            screen_size: [W as f32, H as f32],
+        };
+        let sprite_estimate =
+            levels[current_level].sprite_count() + levels[current_level].starts().len();
+        renderer.sprite_group_add(
+            &tile_tex,
+            vec![Transform::ZERO; sprite_estimate],
+            vec![SheetRegion::ZERO; sprite_estimate],
+            camera,
+        );
+        let player_start = levels[current_level]
+            .starts()
+            .iter()
+            .find(|(t, _, _)| *t == EntityType::Player)
+            .map(|(_, ploc, _)| *ploc + Vec2 { x: 0.0, y: 200.0 })
+            .expect("Start level doesn't put the player anywhere");
+        let mut world = World::default();
+        let player = spawn(&mut world, &EntityType::Player, player_start).unwrap();
+        let mut game = Game {
+            current_level,
+            camera,
+            levels,
+            world,
+            player,
+            touching_obstacle: false,
+            animations: vec![
+                Animation::with_frame(SheetRegion::ZERO),
+                Animation::with_frames(
+                    &[
+                        // PlayerRightIdle
+                        SheetRegion::rect(0, 364, 36, 36),
+                    ],
+                    0.15,
+                )
+                .looped(),
+                Animation::with_frames(
+                    &[
+                        // PlayerRightWalk
+                        SheetRegion::rect(0, 364, 36, 36),
+                        SheetRegion::rect(60, 364, 36, 36),
+                        SheetRegion::rect(120, 364, 36, 36),
+                        SheetRegion::rect(180, 364, 36, 36),
+                        SheetRegion::rect(240, 364, 36, 36),
+                    ],
+                    0.10,
+                )
+                .looped(),
+                // PlayerRightJumpRise
+                Animation::with_frame(SheetRegion::rect(60, 364, 36, 36)),
+                // PlayerRightJumpFall
+                Animation::with_frame(SheetRegion::rect(180, 364, 36, 36)),
+                Animation::with_frames(
+                    &[
+                        // PlayerRightAttack
+                        SheetRegion::rect(120, 364, 36, 36),
+                    ],
+                    0.07,
+                ),
+                Animation::with_frames(
+                    &[
+                        // PlayerLeftIdle
+                        SheetRegion::rect(0, 364, 36, 36),
+                    ],
+                    0.15,
+                )
+                .looped()
+                .flip_horizontal(),
+                Animation::with_frames(
+                    &[
+                        // PlayerLeftWalk
+                        SheetRegion::rect(0, 364, 36, 36),
+                        SheetRegion::rect(60, 364, 36, 36),
+                        SheetRegion::rect(120, 364, 36, 36),
+                        SheetRegion::rect(180, 364, 36, 36),
+                        SheetRegion::rect(240, 364, 36, 36),
+                    ],
+                    0.15,
+                )
+                .looped()
+                .flip_horizontal(),
+                // PlayerLeftJumpRise
+                Animation::with_frame(SheetRegion::rect(60, 364, 36, 36)).flip_horizontal(),
+                // PlayerLeftJumpFall
+                Animation::with_frame(SheetRegion::rect(180, 364, 36, 36)).flip_horizontal(),
+                Animation::with_frames(
+                    &[
+                        // PlayerLeftAttack
+                        SheetRegion::rect(120, 364, 36, 36),
+                    ],
+                    0.07,
+                )
+                .flip_horizontal(),
+            ],
+        };
+        game.enter_level(player_start);
+        game
+    }
+    fn render(&mut self, frend: &mut Renderer) {
+        // make this exactly as big as we need
+        frend.sprite_group_resize(0, self.sprite_count());
+        frend.sprite_group_set_camera(0, self.camera);
+
+        let sprites_used = self.level().render_into(frend, 0);
+        self.world
+            .render_into(frend, 0, sprites_used, &self.animations);
+
+        frend.sprite_group_set_camera(
+            0,
+            Camera2D {
+                screen_pos: [(self.player_pos().x - (W / 4) as f32), 7_f32],
+                screen_size: [W as f32, H as f32],
+            },
+        );
+    }
+    fn simulate(&mut self, input: &Input, dt: f32) {
+        let lw = self.level().width();
+        let lh = self.level().height();
+
+        systems::controls(&mut self.world, input, dt);
+        systems::gravity(&mut self.world, GRAV_ACC, dt);
+        systems::movement(&mut self.world, &self.levels[self.current_level], dt);
+        systems::keep_in_bounds(&mut self.world, (lw * TILE_SZ) as f32, H as f32);
+        systems::tile_collision(&mut self.world, &self.levels[self.current_level]);
+        self.world.update_broadphase();
+        systems::animation(&mut self.world, dt);
+        self.levels[self.current_level].tick(dt);
+
+        // Obstacle collision and response
+        let triggers = self.world.contacts(Tags::PLAYER, Tags::OBSTACLE);
+        if triggers.is_empty() {
+            self.touching_obstacle = false;
+        }
+        // hit obstacle if player has moved, wasn't previously touching obstacle
+        if let Some(&(_, obstacle)) = triggers.first() {
+            if !self.touching_obstacle {
+                self.tp_to_start(obstacle);
+            }
+        }
+
+        let player_pos = self.player_pos();
+        while player_pos.x
+            > self.camera.screen_pos[0] + self.camera.screen_size[0] - SCREEN_FAST_MARGIN
+        {
+            self.camera.screen_pos[0] += 1.0;
+        }
+        while player_pos.x < self.camera.screen_pos[0] + SCREEN_FAST_MARGIN {
+            self.camera.screen_pos[0] -= 1.0;
+        }
+        while player_pos.y
+            > self.camera.screen_pos[1] + self.camera.screen_size[1] - SCREEN_FAST_MARGIN
+        {
+            self.camera.screen_pos[1] += 1.0;
+        }
+        while player_pos.y < self.camera.screen_pos[1] + SCREEN_FAST_MARGIN {
+            self.camera.screen_pos[1] -= 1.0;
+        }
+        self.camera.screen_pos[0] =
+            self.camera.screen_pos[0].clamp(0.0, (lw * TILE_SZ).max(W) as f32 - W as f32);
+        self.camera.screen_pos[1] =
+            self.camera.screen_pos[1].clamp(0.0, (lh * TILE_SZ).max(H) as f32 - H as f32);
+    }
+}
+
+impl Game {
+    fn level(&self) -> &Level {
+        &self.levels[self.current_level]
+    }
+    fn player_pos(&self) -> Vec2 {
+        *self.world.positions.get(self.player).unwrap()
+    }
+    fn enter_level(&mut self, player_pos: Vec2) {
+        // the player carries over between levels, everything else is replaced
+        let old: Vec<Entity> = self
+            .world
+            .entities()
+            .filter(|e| *e != self.player)
+            .collect();
+        for e in old {
+            self.world.despawn(e);
+        }
+        // we will probably enter at a obstacle
+        self.touching_obstacle = true;
+        *self.world.positions.get_mut(self.player).unwrap() = player_pos;
+        for (etype, pos, props) in self.levels[self.current_level].starts().iter() {
+            if *etype != EntityType::Player {
+                if let Some(e) = spawn(&mut self.world, etype, *pos) {
+                    self.world.props.insert(e, props.clone());
+                }
+            }
+        }
+    }
+    fn sprite_count(&self) -> usize {
+        self.level().sprite_count() + self.world.sprite_count()
+    }
+
+    fn tp_to_start(&mut self, obstacle: Entity) {
+        self.touching_obstacle = true;
+        let Destination { level, x, y } = self.world.destinations.get(obstacle).cloned().unwrap();
+        let Some(dest) = self.levels.iter().position(|l| l.name() == level) else {
+            eprintln!("obstacle to invalid room {level}!");
+            return;
+        };
+        let offset = Vec2 {
+            x: TILE_SZ as f32 / 2.0,
+            y: -12.0 + TILE_SZ as f32 / 2.0,
+        };
+        if dest == self.current_level {
+            *self.world.positions.get_mut(self.player).unwrap() =
+                self.level().grid_to_world((x as usize, y as usize)) + offset;
+        } else {
+            self.current_level = dest;
+            self.enter_level(self.level().grid_to_world((x as usize, y as usize)) + offset);
+        }
+    }
+}
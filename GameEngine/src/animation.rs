@@ -1,11 +1,36 @@
+use crate::level::same_region;
 use frenderer::sprites::SheetRegion;
+use std::fmt;
+use std::str::FromStr;
 
 #[allow(unused)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum RepeatMode {
     OneShot,
     Loop,
     PingPong,
 }
+// Level legends name these `once`, `loop` and `pingpong`
+impl FromStr for RepeatMode {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "once" => Ok(Self::OneShot),
+            "loop" => Ok(Self::Loop),
+            "pingpong" => Ok(Self::PingPong),
+            _ => Err(()),
+        }
+    }
+}
+impl fmt::Display for RepeatMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::OneShot => "once",
+            Self::Loop => "loop",
+            Self::PingPong => "pingpong",
+        })
+    }
+}
 impl RepeatMode {
     fn map_t(&self, duration: f32, t: f32) -> f32 {
         match self {
@@ -14,7 +39,7 @@ impl RepeatMode {
             RepeatMode::PingPong => {
                 let quot = t.div_euclid(duration);
                 let rem = t.rem_euclid(duration);
-                if (quot as u32) % 2 == 0 {
+                if (quot as u32).is_multiple_of(2) {
                     rem
                 } else {
                     duration - rem
@@ -23,11 +48,24 @@ impl RepeatMode {
         }
     }
 }
+#[derive(Clone, Debug)]
 pub struct Animation {
     frames: Vec<SheetRegion>,
     timings: Vec<f32>,
     repeat_mode: RepeatMode,
 }
+impl PartialEq for Animation {
+    fn eq(&self, other: &Self) -> bool {
+        self.frames.len() == other.frames.len()
+            && self
+                .frames
+                .iter()
+                .zip(&other.frames)
+                .all(|(a, b)| same_region(a, b))
+            && self.timings == other.timings
+            && self.repeat_mode == other.repeat_mode
+    }
+}
 #[allow(dead_code)]
 impl Animation {
     pub fn with_frame(frame: SheetRegion) -> Self {
@@ -63,6 +101,12 @@ impl Animation {
             ..self
         }
     }
+    pub fn with_repeat_mode(self, repeat_mode: RepeatMode) -> Self {
+        Self {
+            repeat_mode,
+            ..self
+        }
+    }
     pub fn flip_horizontal(self) -> Self {
         Self {
             frames: self
@@ -73,6 +117,15 @@ impl Animation {
             ..self
         }
     }
+    pub fn frames(&self) -> &[SheetRegion] {
+        &self.frames
+    }
+    pub fn timings(&self) -> &[f32] {
+        &self.timings
+    }
+    pub fn repeat_mode(&self) -> RepeatMode {
+        self.repeat_mode
+    }
     pub fn duration(&self) -> f32 {
         self.timings.iter().sum()
     }
@@ -90,3 +143,34 @@ impl Animation {
         None
     }
 }
+
+// Which animation (an index into the game's animation list) an entity is
+// playing, and how far into it they are.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnimationState {
+    pub animation: usize,
+    pub t: f32,
+}
+impl AnimationState {
+    pub fn new(animation: usize) -> Self {
+        Self { animation, t: 0.0 }
+    }
+    pub fn finished(&self, anims: &[Animation]) -> bool {
+        anims[self.animation].sample(self.t).is_none()
+    }
+    pub fn sample(&self, anims: &[Animation]) -> SheetRegion {
+        anims[self.animation]
+            .sample(self.t)
+            .unwrap_or_else(|| anims[self.animation].sample(0.0).unwrap())
+    }
+    pub fn tick(&mut self, dt: f32) {
+        self.t += dt;
+    }
+    pub fn play(&mut self, anim: usize, retrigger: bool) {
+        if anim == self.animation && !retrigger {
+            return;
+        }
+        self.animation = anim;
+        self.t = 0.0;
+    }
+}
@@ -0,0 +1,353 @@
+use crate::level::{EntityType, Level, LevelParseError};
+use assets_manager::{AssetCache, ReloadId};
+use std::fmt;
+
+// The levels of a game and how they lead into each other, read from a
+// manifest asset (usually `content/campaign.txt`) like this:
+//
+//   # levels are listed by asset name, in order
+//   start level1
+//   level1 next=level2
+//   level2
+//   house
+//
+// Finishing a level with a `next` sends you there; finishing one without
+// wins the game.  `start` picks the first level to play (the first one listed
+// if there's no start line).  Levels that are only reached through obstacles,
+// like the house, just need to be listed.
+#[derive(Debug, PartialEq)]
+pub struct Campaign {
+    levels: Vec<Level>,
+    // the asset each level was loaded from
+    files: Vec<String>,
+    next: Vec<Option<usize>>,
+    start: usize,
+    // the version of each level file we last loaded, for hot reloading; None
+    // for campaigns that weren't loaded from the cache
+    reload_ids: Vec<Option<ReloadId>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ManifestErrorKind {
+    // `next` or `start` names a level that isn't listed
+    UnknownLevel,
+    DuplicateLevel,
+    DuplicateStart,
+    UnknownKey,
+    NoLevels,
+}
+
+#[derive(Debug)]
+pub enum CampaignError {
+    // line counts from 1, and token is the offending text
+    Manifest {
+        file: String,
+        line: usize,
+        token: String,
+        kind: ManifestErrorKind,
+    },
+    Level(LevelParseError),
+    // an obstacle in `file` leads to a level the campaign doesn't have
+    Destination {
+        file: String,
+        level: String,
+    },
+}
+
+impl fmt::Display for CampaignError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ManifestErrorKind::*;
+        let (file, line, token, kind) = match self {
+            Self::Level(err) => return err.fmt(f),
+            Self::Destination { file, level } => {
+                return write!(
+                    f,
+                    "{file}.txt: an obstacle leads to level '{level}', which isn't in the campaign"
+                )
+            }
+            Self::Manifest {
+                file,
+                line,
+                token,
+                kind,
+            } => (file, line, token, kind),
+        };
+        write!(f, "{file}:{line}: ")?;
+        match kind {
+            UnknownLevel => write!(f, "level '{token}' isn't in the campaign"),
+            DuplicateLevel => write!(f, "level '{token}' is listed twice"),
+            DuplicateStart => write!(f, "second start line"),
+            UnknownKey => write!(f, "expected next=LEVEL, not '{token}'"),
+            NoLevels => write!(f, "the campaign doesn't list any levels"),
+        }
+    }
+}
+
+impl std::error::Error for CampaignError {}
+
+impl From<LevelParseError> for CampaignError {
+    fn from(err: LevelParseError) -> Self {
+        Self::Level(err)
+    }
+}
+
+// A campaign of levels played in no particular order, starting with the
+// first, where finishing any level wins.  This is what the games did before
+// they had manifests, and it's handy for tests.
+impl From<Vec<Level>> for Campaign {
+    fn from(levels: Vec<Level>) -> Self {
+        assert!(!levels.is_empty(), "A campaign needs at least one level");
+        Self {
+            files: levels.iter().map(|l| l.name().to_string()).collect(),
+            next: vec![None; levels.len()],
+            reload_ids: vec![None; levels.len()],
+            levels,
+            start: 0,
+        }
+    }
+}
+
+impl Campaign {
+    // Loads the manifest `content/{name}.txt` and every level it lists
+    pub fn load(cache: &AssetCache, name: &str) -> Result<Self, CampaignError> {
+        let manifest = cache
+            .load::<String>(name)
+            .unwrap_or_else(|e| panic!("Couldn't access {name}.txt: {e}"));
+        let manifest = manifest.read();
+        let mut campaign = Self::parse(&format!("{name}.txt"), manifest.as_str(), |level| {
+            Level::load(cache, level)
+        })?;
+        campaign.reload_ids = campaign
+            .files
+            .iter()
+            .map(|f| cache.load::<String>(f).ok().map(|h| h.last_reload_id()))
+            .collect();
+        Ok(campaign)
+    }
+    // Re-reads the level files that changed since they were last loaded,
+    // returning the indices of the levels that were replaced.  A level that
+    // fails to parse or has an obstacle to a missing level keeps its old
+    // version and isn't retried until its file changes again; any other
+    // changed levels are picked up on the next call.
+    pub fn reload(&mut self, cache: &AssetCache) -> Result<Vec<usize>, CampaignError> {
+        let mut changed = vec![];
+        for (i, file) in self.files.iter().enumerate() {
+            let Some(old_id) = self.reload_ids[i] else {
+                continue;
+            };
+            let Ok(handle) = cache.load::<String>(file) else {
+                continue;
+            };
+            if handle.last_reload_id() == old_id {
+                continue;
+            }
+            self.reload_ids[i] = Some(handle.last_reload_id());
+            let old = std::mem::replace(&mut self.levels[i], Level::load(cache, file)?);
+            if let Err(err) = check_destinations(&self.levels, &self.files) {
+                self.levels[i] = old;
+                return Err(err);
+            }
+            changed.push(i);
+        }
+        Ok(changed)
+    }
+    // Reads a manifest, calling `load_level` with each level's asset name.
+    // `file` is only used in error messages.
+    pub fn parse(
+        file: &str,
+        manifest: &str,
+        mut load_level: impl FnMut(&str) -> Result<Level, LevelParseError>,
+    ) -> Result<Self, CampaignError> {
+        let error = |line: usize, token: &str, kind| CampaignError::Manifest {
+            file: file.to_string(),
+            line,
+            token: token.to_string(),
+            kind,
+        };
+        let mut files: Vec<String> = vec![];
+        // the line of each level, and its next level's name and line
+        let mut nexts: Vec<Option<(&str, usize)>> = vec![];
+        let mut start = None;
+        let mut line_count = 0;
+        for (line_no, line) in manifest.lines().enumerate() {
+            let line_no = line_no + 1;
+            line_count = line_no;
+            let line = line.split('#').next().unwrap_or("");
+            let mut tokens = line.split_whitespace();
+            let Some(first) = tokens.next() else {
+                continue;
+            };
+            if first == "start" {
+                if start.is_some() {
+                    return Err(error(line_no, first, ManifestErrorKind::DuplicateStart));
+                }
+                let name = tokens
+                    .next()
+                    .ok_or_else(|| error(line_no, "", ManifestErrorKind::UnknownLevel))?;
+                start = Some((name, line_no));
+                continue;
+            }
+            if files.iter().any(|f| f == first) {
+                return Err(error(line_no, first, ManifestErrorKind::DuplicateLevel));
+            }
+            let mut next = None;
+            for token in tokens {
+                match token.split_once('=') {
+                    Some(("next", level)) => next = Some((level, line_no)),
+                    _ => return Err(error(line_no, token, ManifestErrorKind::UnknownKey)),
+                }
+            }
+            files.push(first.to_string());
+            nexts.push(next);
+        }
+        if files.is_empty() {
+            return Err(error(line_count, "", ManifestErrorKind::NoLevels));
+        }
+        let index = |(name, line): (&str, usize)| {
+            files
+                .iter()
+                .position(|f| f == name)
+                .ok_or_else(|| error(line, name, ManifestErrorKind::UnknownLevel))
+        };
+        let next = nexts
+            .into_iter()
+            .map(|next| next.map(index).transpose())
+            .collect::<Result<Vec<_>, _>>()?;
+        let start = start.map(index).transpose()?.unwrap_or(0);
+        let levels = files
+            .iter()
+            .map(|f| load_level(f))
+            .collect::<Result<Vec<_>, _>>()?;
+        check_destinations(&levels, &files)?;
+        Ok(Self {
+            reload_ids: vec![None; levels.len()],
+            levels,
+            files,
+            next,
+            start,
+        })
+    }
+    pub fn levels(&self) -> &[Level] {
+        &self.levels
+    }
+    pub fn level(&self, index: usize) -> &Level {
+        &self.levels[index]
+    }
+    // For ticking the level's animated tiles
+    pub fn level_mut(&mut self, index: usize) -> &mut Level {
+        &mut self.levels[index]
+    }
+    // The asset the level was loaded from
+    pub fn file(&self, index: usize) -> &str {
+        &self.files[index]
+    }
+    pub fn start(&self) -> usize {
+        self.start
+    }
+    // Where finishing this level goes, or None if it's the end of the game
+    pub fn next(&self, index: usize) -> Option<usize> {
+        self.next[index]
+    }
+    // Finds a level by the name in its level file, as obstacles refer to them
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.levels.iter().position(|l| l.name() == name)
+    }
+}
+
+// Obstacles name the level they lead to as it's named in its level file, and
+// that level has to be in the campaign
+fn check_destinations(levels: &[Level], files: &[String]) -> Result<(), CampaignError> {
+    for (level, file) in levels.iter().zip(files) {
+        for (etype, _, _) in level.starts() {
+            if let EntityType::Obstacle(dest, _, _) = etype {
+                if !levels.iter().any(|l| l.name() == dest) {
+                    return Err(CampaignError::Destination {
+                        file: file.clone(),
+                        level: dest.clone(),
+                    });
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(name: &str) -> Result<Level, LevelParseError> {
+        format!("{name} 1 1\n====\nair o 0 0 16 16\n====\nair\n====\nplayer 0 0\n").parse()
+    }
+
+    #[test]
+    fn test_campaign_manifest() {
+        let manifest = "# the main path\nstart one\nzero\none next=two\ntwo next=zero # loops\n";
+        let campaign = Campaign::parse("campaign.txt", manifest, level).unwrap();
+        assert_eq!(campaign.levels().len(), 3);
+        assert_eq!(campaign.start(), 1);
+        assert_eq!(campaign.level(campaign.start()).name(), "one");
+        assert_eq!(campaign.next(1), Some(2));
+        assert_eq!(campaign.next(2), Some(0));
+        assert_eq!(campaign.next(0), None);
+        assert_eq!(campaign.index_of("two"), Some(2));
+        assert_eq!(campaign.file(0), "zero");
+
+        let plain = Campaign::parse("campaign.txt", "zero\none\n", level).unwrap();
+        assert_eq!(plain.start(), 0);
+        assert_eq!(
+            plain,
+            Campaign::from(vec![level("zero").unwrap(), level("one").unwrap()])
+        );
+    }
+
+    #[test]
+    fn test_campaign_errors() {
+        let err = |manifest: &str| {
+            Campaign::parse("campaign.txt", manifest, level)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            err("one next=three\ntwo\n"),
+            "campaign.txt:1: level 'three' isn't in the campaign"
+        );
+        assert_eq!(
+            err("one\ntwo\none\n"),
+            "campaign.txt:3: level 'one' is listed twice"
+        );
+        assert_eq!(
+            err("start two\none\n"),
+            "campaign.txt:1: level 'two' isn't in the campaign"
+        );
+        assert_eq!(
+            err("one after=two\n"),
+            "campaign.txt:1: expected next=LEVEL, not 'after=two'"
+        );
+        assert_eq!(
+            err("# nothing\n"),
+            "campaign.txt:1: the campaign doesn't list any levels"
+        );
+        // obstacles have to lead somewhere in the campaign
+        let to_house = |name: &str| {
+            format!("{name} 2 1\n====\nair o 0 0 16 16\n====\nair air\n====\nplayer 0 0\nobstacle house 0 0 1 0\n")
+                .parse()
+        };
+        let err = Campaign::parse("campaign.txt", "one\n", to_house).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "one.txt: an obstacle leads to level 'house', which isn't in the campaign"
+        );
+        let load = |name: &str| {
+            if name == "house" {
+                level(name)
+            } else {
+                to_house(name)
+            }
+        };
+        assert!(Campaign::parse("campaign.txt", "one\nhouse\n", load).is_ok());
+        // level errors come through as they are
+        let broken = Campaign::parse("campaign.txt", "one\n", |_| "broken".parse()).unwrap_err();
+        assert!(matches!(broken, CampaignError::Level(_)));
+    }
+}
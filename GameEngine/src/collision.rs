@@ -0,0 +1,181 @@
+use crate::geom::*;
+use crate::level::Level;
+use crate::TILE_SZ;
+use std::collections::HashMap;
+
+pub struct Contact {
+    pub a_index: usize,
+    pub a_rect: Rect,
+    pub b_index: usize,
+    pub b_rect: Rect,
+    pub displacement: Vec2,
+}
+
+pub fn gather_contacts_tiles(rects: &[Rect], level: &Level, contacts: &mut Vec<Contact>) {
+    for (rect_i, rect) in rects.iter().enumerate() {
+        for (tr, _td) in level.tiles_within(*rect).filter(|(_, td)| td.flags.solid) {
+            if let Some(displacement) = rect.overlap(tr) {
+                contacts.push(Contact {
+                    a_index: rect_i,
+                    a_rect: *rect,
+                    b_index: 0,
+                    b_rect: tr,
+                    displacement,
+                });
+            }
+        }
+    }
+}
+
+pub fn gather_contacts(rects_a: &[Rect], rects_b: &[Rect], contacts: &mut Vec<Contact>) {
+    for (a_i, a) in rects_a.iter().enumerate() {
+        for (b_i, b) in rects_b.iter().enumerate() {
+            if let Some(displacement) = a.overlap(*b) {
+                contacts.push(Contact {
+                    a_index: a_i,
+                    a_rect: *a,
+                    b_index: b_i,
+                    b_rect: *b,
+                    displacement,
+                });
+            }
+        }
+    }
+}
+
+// computes the displacement to resolve the contact
+pub fn compute_disp(a: Rect, b: Rect) -> Vec2 {
+    let mut displacement = a.overlap(b).unwrap_or(Vec2 { x: 0.0, y: 0.0 });
+    if displacement.x < displacement.y {
+        displacement.y = 0.0;
+    } else {
+        displacement.x = 0.0;
+    }
+    // rectangle a is left of rectangle b, displacement becomes negative
+    // rectabnel a is bellow rectangle b, displacement becomes negative
+    if a.x < b.x {
+        displacement.x = -displacement.x;
+    }
+    if a.y < b.y {
+        displacement.y = -displacement.y;
+    }
+    displacement
+}
+
+// A broadphase for lots of rects: each is filed under every TILE_SZ cell it
+// covers, so finding what overlaps a rect only looks at the things in the
+// cells under it instead of everything.  Rebuild it whenever things move.
+#[derive(Clone, Debug)]
+pub struct SpatialHash<T> {
+    cells: HashMap<(i32, i32), Vec<usize>>,
+    items: Vec<(T, Rect)>,
+}
+
+impl<T> Default for SpatialHash<T> {
+    fn default() -> Self {
+        Self {
+            cells: HashMap::new(),
+            items: vec![],
+        }
+    }
+}
+
+impl<T: Copy> SpatialHash<T> {
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.items.clear();
+    }
+    pub fn insert(&mut self, item: T, rect: Rect) {
+        if rect.is_empty() {
+            return;
+        }
+        let index = self.items.len();
+        self.items.push((item, rect));
+        for cell in cells_under(rect) {
+            self.cells.entry(cell).or_default().push(index);
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+    // Everything whose rect overlaps (or touches) `rect`, in the order they
+    // were inserted
+    pub fn query(&self, rect: Rect) -> Vec<(T, Rect)> {
+        if rect.is_empty() {
+            return vec![];
+        }
+        let mut found: Vec<usize> = cells_under(rect)
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+            .collect();
+        // things covering several cells are found once for each
+        found.sort_unstable();
+        found.dedup();
+        found
+            .into_iter()
+            .map(|index| self.items[index])
+            .filter(|(_, other)| rect.overlap(*other).is_some())
+            .collect()
+    }
+}
+
+// The cells a rect covers, counting its right and top edges so rects that
+// only touch still share a cell
+fn cells_under(rect: Rect) -> impl Iterator<Item = (i32, i32)> {
+    let cell = |v: f32| (v / TILE_SZ as f32).floor() as i32;
+    let (x0, x1) = (cell(rect.x), cell(rect.x + rect.w as f32));
+    let (y0, y1) = (cell(rect.y), cell(rect.y + rect.h as f32));
+    (x0..=x1).flat_map(move |x| (y0..=y1).map(move |y| (x, y)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spatial_hash() {
+        let rect = |x: f32, y: f32, w: u16, h: u16| Rect { x, y, w, h };
+        let mut hash = SpatialHash::default();
+        hash.insert('a', rect(0.0, 0.0, 8, 8));
+        // spans lots of cells, but is only found once
+        hash.insert('b', rect(4.0, 4.0, 100, 40));
+        // touches `a` edge to edge
+        hash.insert('c', rect(8.0, -8.0, 8, 8));
+        hash.insert('d', rect(-500.0, 300.0, 16, 16));
+        hash.insert('e', Rect::ZERO);
+        assert_eq!(hash.len(), 4);
+        let found = |r: Rect| {
+            hash.query(r)
+                .into_iter()
+                .map(|(c, _)| c)
+                .collect::<String>()
+        };
+        assert_eq!(found(rect(0.0, 0.0, 8, 8)), "abc");
+        assert_eq!(found(rect(90.0, 30.0, 4, 4)), "b");
+        assert_eq!(found(rect(-490.0, 310.0, 1, 1)), "d");
+        assert_eq!(found(rect(200.0, 200.0, 16, 16)), "");
+
+        // it finds the same pairs as checking every rect against every other
+        let rects: Vec<Rect> = (0..60)
+            .map(|i| rect((i * 37 % 200) as f32, (i * 11 % 50) as f32, 4 + i % 20, 6))
+            .collect();
+        let mut expected = vec![];
+        gather_contacts(&rects, &rects, &mut expected);
+        let mut hash = SpatialHash::default();
+        for (i, r) in rects.iter().enumerate() {
+            hash.insert(i, *r);
+        }
+        let pairs: Vec<(usize, usize)> = rects
+            .iter()
+            .enumerate()
+            .flat_map(|(a, r)| hash.query(*r).into_iter().map(move |(b, _)| (a, b)))
+            .collect();
+        let expected: Vec<(usize, usize)> =
+            expected.iter().map(|c| (c.a_index, c.b_index)).collect();
+        assert_eq!(pairs, expected);
+    }
+}
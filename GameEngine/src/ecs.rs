@@ -0,0 +1,448 @@
+use crate::animation::{Animation, AnimationState};
+use crate::collision::SpatialHash;
+use crate::geom::*;
+use crate::level::{Properties, TileFlags};
+use frenderer::{
+    input::Key,
+    sprites::{SheetRegion, Transform},
+    Renderer,
+};
+
+// Entities are just indices into the component storages.  We never reuse an
+// index until the world is cleared (e.g. on entering a level), so a stale
+// Entity can't accidentally point at something new.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Entity(usize);
+
+impl Entity {
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+// One optional component per entity.  Games can make their own storages for
+// components the engine doesn't know about.
+#[derive(Clone, Debug)]
+pub struct Storage<T> {
+    items: Vec<Option<T>>,
+}
+
+impl<T> Default for Storage<T> {
+    fn default() -> Self {
+        Self { items: vec![] }
+    }
+}
+
+impl<T> Storage<T> {
+    pub fn insert(&mut self, e: Entity, val: T) {
+        if self.items.len() <= e.0 {
+            self.items.resize_with(e.0 + 1, || None);
+        }
+        self.items[e.0] = Some(val);
+    }
+    pub fn remove(&mut self, e: Entity) -> Option<T> {
+        self.items.get_mut(e.0).and_then(|c| c.take())
+    }
+    pub fn get(&self, e: Entity) -> Option<&T> {
+        self.items.get(e.0).and_then(|c| c.as_ref())
+    }
+    pub fn get_mut(&mut self, e: Entity) -> Option<&mut T> {
+        self.items.get_mut(e.0).and_then(|c| c.as_mut())
+    }
+    pub fn contains(&self, e: Entity) -> bool {
+        self.get(e).is_some()
+    }
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+    pub fn len(&self) -> usize {
+        self.items.iter().filter(|c| c.is_some()).count()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.items
+            .iter()
+            .enumerate()
+            .filter_map(|(i, c)| c.as_ref().map(|c| (Entity(i), c)))
+    }
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        self.items
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, c)| c.as_mut().map(|c| (Entity(i), c)))
+    }
+}
+
+// What kind of thing an entity is, as a set of bits so an entity can be
+// several things at once.  Games can use bits past the ones defined here.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Tags(pub u32);
+
+impl Tags {
+    pub const NONE: Tags = Tags(0);
+    pub const PLAYER: Tags = Tags(1 << 0);
+    pub const ENEMY: Tags = Tags(1 << 1);
+    pub const OBSTACLE: Tags = Tags(1 << 2);
+    pub const MUD: Tags = Tags(1 << 3);
+
+    pub fn contains(self, other: Tags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for Tags {
+    type Output = Tags;
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Tags(self.0 | rhs.0)
+    }
+}
+
+// The collision box relative to the entity's position
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Collider {
+    pub offset: Vec2,
+    pub w: u16,
+    pub h: u16,
+}
+
+impl Collider {
+    pub fn rect(&self, pos: Vec2) -> Rect {
+        Rect {
+            x: pos.x + self.offset.x,
+            y: pos.y + self.offset.y,
+            w: self.w,
+            h: self.h,
+        }
+    }
+}
+
+// Entities with a body get pulled down by gravity and pushed out of solid tiles
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Body {
+    // multiplies the gravity passed to the gravity system; 0 floats
+    pub gravity: f32,
+    pub grounded: bool,
+    // the flags of every tile it touched in the last tile_collision
+    pub touching: TileFlags,
+}
+
+// What to draw relative to the entity's position
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprite {
+    pub anim: AnimationState,
+    pub offset: Vec2,
+    pub w: u16,
+    pub h: u16,
+}
+
+impl Sprite {
+    pub fn trf(&self, pos: Vec2) -> Transform {
+        Transform {
+            w: self.w,
+            h: self.h,
+            x: pos.x + self.offset.x,
+            y: pos.y + self.offset.y,
+            rot: 0.0,
+        }
+    }
+}
+
+// Which of the game's animations to play for each movement state
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Animator {
+    pub idle: usize,
+    pub walk: usize,
+    pub jump_rise: usize,
+    pub jump_fall: usize,
+}
+
+// Keyboard-driven running and jumping
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Controller {
+    pub jump_key: Key,
+    pub jump_vel: f32,
+    pub jump_time: f32,
+    // if false you can only jump while standing on something
+    pub air_jumps: bool,
+    // forward acceleration while running, and the top speed
+    pub run_acc: f32,
+    pub max_speed: f32,
+    pub jumping: bool,
+    pub jump_timer: f32,
+}
+
+impl Controller {
+    pub fn new(jump_key: Key, jump_vel: f32, jump_time: f32) -> Self {
+        Self {
+            jump_key,
+            jump_vel,
+            jump_time,
+            air_jumps: false,
+            run_acc: 0.0,
+            max_speed: f32::INFINITY,
+            jumping: false,
+            jump_timer: 0.0,
+        }
+    }
+}
+
+// Walks back and forth, turning around every few seconds, or at the ends of
+// `bounds` (a range of x positions) if it has them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Patrol {
+    pub speed: f32,
+    pub dir: f32,
+    pub change_dir_timer: f32,
+    pub bounds: Option<(f32, f32)>,
+}
+
+// Where touching this entity sends you: a level name and grid position there
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Destination {
+    pub level: String,
+    pub x: u16,
+    pub y: u16,
+}
+
+#[derive(Default)]
+pub struct World {
+    next: usize,
+    alive: Storage<()>,
+    pub positions: Storage<Vec2>,
+    pub velocities: Storage<Vec2>,
+    pub colliders: Storage<Collider>,
+    pub bodies: Storage<Body>,
+    pub sprites: Storage<Sprite>,
+    pub animators: Storage<Animator>,
+    pub controllers: Storage<Controller>,
+    pub patrols: Storage<Patrol>,
+    pub destinations: Storage<Destination>,
+    pub tags: Storage<Tags>,
+    // the properties from the level start an entity was spawned for
+    pub props: Storage<Properties>,
+    // every collider, as of the last update_broadphase
+    broadphase: SpatialHash<Entity>,
+    // each body and obstacle it passed through in the last systems::movement
+    pub(crate) passed: Vec<(Entity, Entity)>,
+}
+
+impl World {
+    pub fn spawn(&mut self, pos: Vec2) -> Entity {
+        let e = Entity(self.next);
+        self.next += 1;
+        self.alive.insert(e, ());
+        self.positions.insert(e, pos);
+        e
+    }
+    pub fn despawn(&mut self, e: Entity) {
+        self.alive.remove(e);
+        self.positions.remove(e);
+        self.velocities.remove(e);
+        self.colliders.remove(e);
+        self.bodies.remove(e);
+        self.sprites.remove(e);
+        self.animators.remove(e);
+        self.controllers.remove(e);
+        self.patrols.remove(e);
+        self.destinations.remove(e);
+        self.tags.remove(e);
+        self.props.remove(e);
+        self.passed.retain(|(a, b)| *a != e && *b != e);
+    }
+    // Removes every entity, e.g. before spawning a new level's starts
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+    pub fn is_alive(&self, e: Entity) -> bool {
+        self.alive.contains(e)
+    }
+    pub fn entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.alive.iter().map(|(e, _)| e)
+    }
+    pub fn len(&self) -> usize {
+        self.alive.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.alive.is_empty()
+    }
+    // Moves every entity at once, like when a streamed level re-bases its
+    // coordinates
+    pub fn translate(&mut self, delta: Vec2) {
+        for (_, pos) in self.positions.iter_mut() {
+            *pos += delta;
+        }
+    }
+    pub fn has_tag(&self, e: Entity, tag: Tags) -> bool {
+        self.tags.get(e).is_some_and(|t| t.contains(tag))
+    }
+    pub fn with_tag(&self, tag: Tags) -> impl Iterator<Item = Entity> + '_ {
+        self.tags
+            .iter()
+            .filter(move |(_, t)| t.contains(tag))
+            .map(|(e, _)| e)
+    }
+    // The entity's collision box in world space
+    pub fn rect(&self, e: Entity) -> Option<Rect> {
+        Some(self.colliders.get(e)?.rect(*self.positions.get(e)?))
+    }
+    // Files every collider by where it is now.  Games call this each frame
+    // after the movement systems, before looking for contacts.
+    pub fn update_broadphase(&mut self) {
+        self.broadphase.clear();
+        for (e, col) in self.colliders.iter() {
+            if let Some(pos) = self.positions.get(e) {
+                self.broadphase.insert(e, col.rect(*pos));
+            }
+        }
+    }
+    // Entities tagged `tag` whose collider overlaps `rect`.  Like contacts,
+    // this only finds things where they were at the last update_broadphase.
+    pub fn within(&self, rect: Rect, tag: Tags) -> Vec<Entity> {
+        self.broadphase
+            .query(rect)
+            .into_iter()
+            .map(|(e, _)| e)
+            .filter(|e| self.has_tag(*e, tag))
+            .filter(|e| self.rect(*e).is_some_and(|r| rect.overlap(r).is_some()))
+            .collect()
+    }
+    // Every overlapping pair of an entity tagged `a` with another one tagged
+    // `b`, and every body tagged `a` that passed right through an obstacle
+    // tagged `b` in the last systems::movement.  Pairs that fit either way
+    // round (like two enemies with contacts(ENEMY, ENEMY)) only come once,
+    // lower entity first.
+    pub fn contacts(&self, a: Tags, b: Tags) -> Vec<(Entity, Entity)> {
+        let reported_as = |e: Entity, o: Entity| o < e && self.has_tag(o, a) && self.has_tag(e, b);
+        let mut pairs: Vec<(Entity, Entity)> = self
+            .with_tag(a)
+            .filter_map(|e| Some((e, self.rect(e)?)))
+            .flat_map(|(e, rect)| {
+                self.within(rect, b)
+                    .into_iter()
+                    .filter(move |o| *o != e && !reported_as(e, *o))
+                    .map(move |o| (e, o))
+            })
+            .collect();
+        for (e, o) in self.passed.iter().copied() {
+            let known = pairs.contains(&(e, o)) || pairs.contains(&(o, e));
+            if !known && self.has_tag(e, a) && self.has_tag(o, b) {
+                pairs.push((e, o));
+            }
+        }
+        pairs
+    }
+    pub fn sprite_count(&self) -> usize {
+        self.sprites.len()
+    }
+    // Writes every sprite into the given group starting at `offset`, returning
+    // how many sprites were written.
+    pub fn render_into(
+        &self,
+        frend: &mut Renderer,
+        group: usize,
+        offset: usize,
+        anims: &[Animation],
+    ) -> usize {
+        let count = self.sprite_count();
+        let (trfs, uvs) = frend.sprites_mut(group, offset..offset + count);
+        for ((e, sprite), (trf, uv)) in self.sprites.iter().zip(trfs.iter_mut().zip(uvs.iter_mut()))
+        {
+            *trf = sprite.trf(
+                self.positions
+                    .get(e)
+                    .copied()
+                    .unwrap_or(Vec2 { x: 0.0, y: 0.0 }),
+            );
+            *uv = if anims.is_empty() {
+                SheetRegion::ZERO
+            } else {
+                sprite.anim.sample(anims)
+            };
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_storage_and_tags() {
+        let mut world = World::default();
+        let a = world.spawn(Vec2 { x: 0.0, y: 0.0 });
+        let b = world.spawn(Vec2 { x: 4.0, y: 0.0 });
+        let c = world.spawn(Vec2 { x: 100.0, y: 0.0 });
+        let collider = Collider {
+            offset: Vec2 { x: 0.0, y: 0.0 },
+            w: 8,
+            h: 8,
+        };
+        for e in [a, b, c] {
+            world.colliders.insert(e, collider);
+        }
+        world.tags.insert(a, Tags::PLAYER);
+        world.tags.insert(b, Tags::OBSTACLE);
+        world.tags.insert(c, Tags::OBSTACLE | Tags::MUD);
+        assert_eq!(world.with_tag(Tags::OBSTACLE).count(), 2);
+        assert!(world.has_tag(c, Tags::MUD));
+        world.update_broadphase();
+        assert_eq!(
+            world.within(
+                Rect {
+                    x: 90.0,
+                    y: 0.0,
+                    w: 16,
+                    h: 4
+                },
+                Tags::MUD
+            ),
+            vec![c]
+        );
+        assert_eq!(world.contacts(Tags::PLAYER, Tags::OBSTACLE), vec![(a, b)]);
+        world.despawn(b);
+        assert!(!world.is_alive(b));
+        assert!(world.contacts(Tags::PLAYER, Tags::OBSTACLE).is_empty());
+        assert_eq!(world.len(), 2);
+        // indices aren't reused until the world is cleared
+        assert_ne!(world.spawn(Vec2 { x: 0.0, y: 0.0 }), b);
+        world.clear();
+        assert!(world.is_empty());
+    }
+    #[test]
+    fn test_contact_pairs() {
+        let mut world = World::default();
+        let collider = Collider {
+            offset: Vec2 { x: 0.0, y: 0.0 },
+            w: 8,
+            h: 8,
+        };
+        let enemies: Vec<Entity> = [0.0, 4.0, 6.0]
+            .map(|x| {
+                let e = world.spawn(Vec2 { x, y: 0.0 });
+                world.colliders.insert(e, collider);
+                world.tags.insert(e, Tags::ENEMY | Tags::OBSTACLE);
+                e
+            })
+            .to_vec();
+        world.update_broadphase();
+        let (a, b, c) = (enemies[0], enemies[1], enemies[2]);
+        // nothing touches itself, and each pair comes once
+        let mut pairs = world.contacts(Tags::ENEMY, Tags::ENEMY);
+        pairs.sort();
+        assert_eq!(pairs, vec![(a, b), (a, c), (b, c)]);
+        let mut pairs = world.contacts(Tags::ENEMY, Tags::OBSTACLE);
+        pairs.sort();
+        assert_eq!(pairs, vec![(a, b), (a, c), (b, c)]);
+        // unless only one way round fits
+        world.tags.insert(c, Tags::OBSTACLE);
+        let mut pairs = world.contacts(Tags::ENEMY, Tags::OBSTACLE);
+        pairs.sort();
+        assert_eq!(pairs, vec![(a, b), (a, c), (b, c)]);
+        world.tags.insert(a, Tags::OBSTACLE);
+        let mut pairs = world.contacts(Tags::ENEMY, Tags::OBSTACLE);
+        pairs.sort();
+        assert_eq!(pairs, vec![(b, a), (b, c)]);
+    }
+}
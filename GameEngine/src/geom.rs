@@ -45,6 +45,51 @@ impl Rect {
     pub fn is_empty(&self) -> bool {
         self.w == 0 || self.h == 0
     }
+    // Where this rect, moving by `delta`, first runs into `other`, however
+    // far it moves.  Rects that already overlap, or that only slide along or
+    // graze each other's edges, don't hit.
+    pub fn sweep(&self, delta: Vec2, other: Rect) -> Option<Hit> {
+        if self.is_empty() || other.is_empty() {
+            return None;
+        }
+        // the part of the move (as fractions of it) each axis overlaps for
+        let axis = |pos: f32, size: u16, d: f32, other: f32, other_size: u16| {
+            let (lo, hi) = (other - size as f32, other + other_size as f32);
+            if d == 0.0 {
+                (pos > lo && pos < hi).then_some((f32::NEG_INFINITY, f32::INFINITY))
+            } else {
+                let (a, b) = ((lo - pos) / d, (hi - pos) / d);
+                Some((a.min(b), a.max(b)))
+            }
+        };
+        let (x_in, x_out) = axis(self.x, self.w, delta.x, other.x, other.w)?;
+        let (y_in, y_out) = axis(self.y, self.h, delta.y, other.y, other.h)?;
+        let (time, exit) = (x_in.max(y_in), x_out.min(y_out));
+        if time >= exit || !(0.0..=1.0).contains(&time) {
+            return None;
+        }
+        // hitting a corner square on counts as landing on (or bumping) it
+        let normal = if x_in > y_in {
+            Vec2 {
+                x: -delta.x.signum(),
+                y: 0.0,
+            }
+        } else {
+            Vec2 {
+                x: 0.0,
+                y: -delta.y.signum(),
+            }
+        };
+        Some(Hit { time, normal })
+    }
+}
+
+// How far through its move a swept rect got before it hit something (0 to
+// 1), and which way the thing it hit faces
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Hit {
+    pub time: f32,
+    pub normal: Vec2,
 }
 impl std::ops::Add for Vec2 {
     type Output = Vec2;
@@ -76,3 +121,48 @@ impl Vec2 {
         self.x * self.x + self.y * self.y
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sweep() {
+        let tile = Rect {
+            x: 32.0,
+            y: 0.0,
+            w: 16,
+            h: 16,
+        };
+        let rect = Rect {
+            x: 0.0,
+            y: 0.0,
+            w: 16,
+            h: 16,
+        };
+        // far enough in one frame to pass right through it
+        let hit = rect.sweep(Vec2 { x: 64.0, y: 0.0 }, tile).unwrap();
+        assert_eq!(hit.time, 0.25);
+        assert_eq!(hit.normal, Vec2 { x: -1.0, y: 0.0 });
+        // not far enough, or going the other way
+        assert_eq!(rect.sweep(Vec2 { x: 8.0, y: 0.0 }, tile), None);
+        assert_eq!(rect.sweep(Vec2 { x: -64.0, y: 0.0 }, tile), None);
+
+        // landing on top, from resting on it, and sliding along it
+        let above = Rect { y: 48.0, ..tile };
+        let hit = above.sweep(Vec2 { x: 4.0, y: -64.0 }, tile).unwrap();
+        assert_eq!(hit.time, 0.5);
+        assert_eq!(hit.normal, Vec2 { x: 0.0, y: 1.0 });
+        let resting = Rect { y: 16.0, ..tile };
+        assert_eq!(
+            resting.sweep(Vec2 { x: 0.0, y: -1.0 }, tile).unwrap().time,
+            0.0
+        );
+        assert_eq!(resting.sweep(Vec2 { x: 8.0, y: 0.0 }, tile), None);
+        // overlapping already is left for tile_collision to push out
+        assert_eq!(
+            Rect { x: 30.0, ..rect }.sweep(Vec2 { x: 8.0, y: 0.0 }, tile),
+            None
+        );
+    }
+}
@@ -1,6 +1,7 @@
 pub type Coord = (usize, usize);
 
 #[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Grid<T> {
     width: usize,
     height: usize,
@@ -37,6 +38,25 @@ impl<T> Grid<T> {
     pub fn row_iter(&self) -> impl Iterator<Item = &[T]> {
         self.storage.chunks(self.width)
     }
+    pub fn row(&self, y: usize) -> Option<&[T]> {
+        (y < self.height).then(|| &self.storage[y * self.width..(y + 1) * self.width])
+    }
+    // Drops the first n columns and adds n new ones on the right, with
+    // `fill(y)` for each new cell in row y
+    pub fn scroll_left(&mut self, n: usize, mut fill: impl FnMut(usize) -> T) {
+        let n = n.min(self.width);
+        let old = std::mem::take(&mut self.storage).into_vec();
+        let mut cells = Vec::with_capacity(old.len());
+        for (i, cell) in old.into_iter().enumerate() {
+            if i % self.width >= n {
+                cells.push(cell);
+            }
+            if i % self.width == self.width - 1 {
+                cells.extend((0..n).map(|_| fill(i / self.width)));
+            }
+        }
+        self.storage = cells.into_boxed_slice();
+    }
     pub fn get_index(&self, idx: usize) -> Option<&T> {
         self.storage.get(idx)
     }
@@ -184,6 +204,17 @@ mod tests {
         }
     }
     #[test]
+    fn test_scroll_left() {
+        let mut grid = Grid::new(4, 2, 0..8);
+        grid.scroll_left(3, |y| 10 + y);
+        assert_eq!(
+            grid.row_iter().collect::<Vec<_>>(),
+            vec![&[3, 10, 10, 10][..], &[7, 11, 11, 11][..]]
+        );
+        grid.scroll_left(9, |_| 0);
+        assert!(grid.row_iter().all(|row| row == [0; 4]));
+    }
+    #[test]
     fn test_neighbors() {
         let grid = Grid::new(64, 32, vec![0; 64 * 32]);
         assert_eq!(grid.neighbors_4(0, 0).count(), 2);
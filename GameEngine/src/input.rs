@@ -0,0 +1,47 @@
+use frenderer::input::{Input, Key};
+use std::collections::HashSet;
+
+/// Anything simulation code can ask "is this key held?".  The runner hands
+/// games a frenderer `Input`, but tests and tools can drive a simulation with
+/// `HeldKeys` (or their own source) without a window.
+pub trait InputSource {
+    fn is_key_down(&self, key: Key) -> bool;
+}
+
+impl InputSource for Input {
+    fn is_key_down(&self, key: Key) -> bool {
+        Input::is_key_down(self, key)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct HeldKeys {
+    keys: HashSet<Key>,
+}
+
+impl HeldKeys {
+    pub fn new(keys: &[Key]) -> Self {
+        Self {
+            keys: keys.iter().copied().collect(),
+        }
+    }
+    pub fn press(&mut self, key: Key) {
+        self.keys.insert(key);
+    }
+    pub fn release(&mut self, key: Key) {
+        self.keys.remove(&key);
+    }
+    pub fn set(&mut self, key: Key, down: bool) {
+        if down {
+            self.press(key);
+        } else {
+            self.release(key);
+        }
+    }
+}
+
+impl InputSource for HeldKeys {
+    fn is_key_down(&self, key: Key) -> bool {
+        self.keys.contains(&key)
+    }
+}
@@ -1,335 +1,1238 @@
+use crate::animation::{Animation, RepeatMode};
 use crate::geom::*;
 use crate::grid::{self, Grid};
 use crate::TILE_SZ;
+use assets_manager::AssetCache;
 use frenderer::{
-    sprites::{SheetRegion, Transform},
+    sprites::{Camera2D, SheetRegion, Transform},
     Renderer,
 };
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::ops::Range;
 use std::str::FromStr;
 
-#[derive(Debug, PartialEq, Eq)]
+// Tiles drawn past each edge of the camera by render_visible_into
+pub const VISIBLE_MARGIN: usize = 2;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum EntityType {
     Player,
-    // Enemy,
+    Enemy,
     // which level, grid x in dest level, grid y in dest level
     Obstacle(String, u16, u16),
+    Mud,
+}
+
+// What a tile does to things touching it.  In a legend line these are the
+// FLAGS: `o` for none, `s` for solid, or a comma-separated list of `s`,
+// `oneway`, `hazard`, `slow=COEFF`, `bouncy` and `goal`, like `s,bouncy`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TileFlags {
+    pub solid: bool,
+    // solid only for things landing on it from above
+    pub one_way: bool,
+    // sends the player back to the start
+    pub hazard: bool,
+    // multiplies the speed of anything moving through it
+    pub slow: Option<f32>,
+    // bounces things landing on it back up
+    pub bouncy: bool,
+    pub goal: bool,
+}
+
+impl TileFlags {
+    pub const OPEN: Self = Self {
+        solid: false,
+        one_way: false,
+        hazard: false,
+        slow: None,
+        bouncy: false,
+        goal: false,
+    };
+    pub const SOLID: Self = Self {
+        solid: true,
+        ..Self::OPEN
+    };
+    // Everything either set of flags has; the slowest of two slow tiles wins
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            solid: self.solid || other.solid,
+            one_way: self.one_way || other.one_way,
+            hazard: self.hazard || other.hazard,
+            slow: match (self.slow, other.slow) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            },
+            bouncy: self.bouncy || other.bouncy,
+            goal: self.goal || other.goal,
+        }
+    }
+}
+
+impl FromStr for TileFlags {
+    type Err = LevelParseErrorKind;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut flags = Self::OPEN;
+        for flag in s.to_lowercase().split(',') {
+            match flag.split_once('=') {
+                Some(("slow", coeff)) => {
+                    let coeff = coeff
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|c| *c >= 0.0)
+                        .ok_or(LevelParseErrorKind::BadNumber("slow coefficient"))?;
+                    flags.slow = Some(coeff);
+                }
+                Some(_) => return Err(LevelParseErrorKind::UnknownFlags),
+                None => match flag {
+                    // `o` only makes sense on its own
+                    "o" if s.len() == 1 => {}
+                    "s" | "solid" => flags.solid = true,
+                    "oneway" => flags.one_way = true,
+                    "hazard" => flags.hazard = true,
+                    "bouncy" => flags.bouncy = true,
+                    "goal" => flags.goal = true,
+                    _ => return Err(LevelParseErrorKind::UnknownFlags),
+                },
+            }
+        }
+        Ok(flags)
+    }
+}
+
+// Writes flags the way `from_str` reads them, using `o` and `s` when they're
+// all there is
+impl fmt::Display for TileFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut words = vec![];
+        if self.solid {
+            words.push("s".to_string());
+        }
+        if self.one_way {
+            words.push("oneway".to_string());
+        }
+        if self.hazard {
+            words.push("hazard".to_string());
+        }
+        if let Some(coeff) = self.slow {
+            words.push(format!("slow={coeff}"));
+        }
+        if self.bouncy {
+            words.push("bouncy".to_string());
+        }
+        if self.goal {
+            words.push("goal".to_string());
+        }
+        if words.is_empty() {
+            f.write_str("o")
+        } else {
+            f.write_str(&words.join(","))
+        }
+    }
+}
+
+// One value of a start's `key=value` properties.  Values are typed by how
+// they look: `true`/`false`, then whole numbers, then other numbers, and
+// anything else is a string.  A string in double quotes (with `\"` and `\\`
+// escapes) is always a string, so it can hold spaces or look like a number.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PropValue {
+    Bool(bool),
+    Int(i64),
+    Float(f32),
+    Str(String),
+}
+
+impl FromStr for PropValue {
+    type Err = std::convert::Infallible;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(if let Some(unquoted) = unquote(s) {
+            Self::Str(unquoted)
+        } else if let Ok(b) = s.parse() {
+            Self::Bool(b)
+        } else if let Ok(i) = s.parse() {
+            Self::Int(i)
+        } else if let Some(f) = s
+            .parse()
+            .ok()
+            .filter(|_| s.contains(|c: char| c.is_ascii_digit()))
+        {
+            Self::Float(f)
+        } else {
+            Self::Str(s.to_string())
+        })
+    }
+}
+
+impl fmt::Display for PropValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bool(b) => write!(f, "{b}"),
+            Self::Int(i) => write!(f, "{i}"),
+            // Debug keeps the decimal point on whole numbers, so they read back as floats
+            Self::Float(x) => write!(f, "{x:?}"),
+            Self::Str(s) if !needs_quotes(s) => f.write_str(s),
+            Self::Str(s) => {
+                f.write_str("\"")?;
+                for c in s.chars() {
+                    if c == '"' || c == '\\' {
+                        f.write_str("\\")?;
+                    }
+                    write!(f, "{c}")?;
+                }
+                f.write_str("\"")
+            }
+        }
+    }
+}
+
+// Strings that wouldn't read back as themselves if written bare
+fn needs_quotes(s: &str) -> bool {
+    s.is_empty()
+        || s.contains(|c: char| c.is_whitespace() || c == '"')
+        || !matches!(s.parse(), Ok(PropValue::Str(_)))
+}
+
+// The contents of a `"..."` string, or None if `s` isn't exactly one
+fn unquote(s: &str) -> Option<String> {
+    let mut chars = s.strip_prefix('"')?.chars();
+    let mut out = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.push(chars.next()?),
+            '"' => return chars.next().is_none().then_some(out),
+            c => out.push(c),
+        }
+    }
+    None
+}
+
+// The `key=value` properties at the end of a start line, for tuning one
+// entity at a time (like `enemy 4 5 speed=40`).  The getters return None if
+// the key is missing or holds the wrong type; ints are fine as floats.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Properties(BTreeMap<String, PropValue>);
+
+impl Properties {
+    // Returns the old value if `key` was already set
+    pub fn insert(&mut self, key: &str, value: PropValue) -> Option<PropValue> {
+        self.0.insert(key.to_string(), value)
+    }
+    pub fn get(&self, key: &str) -> Option<&PropValue> {
+        self.0.get(key)
+    }
+    pub fn bool(&self, key: &str) -> Option<bool> {
+        match self.get(key)? {
+            PropValue::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+    pub fn int(&self, key: &str) -> Option<i64> {
+        match self.get(key)? {
+            PropValue::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+    pub fn float(&self, key: &str) -> Option<f32> {
+        match self.get(key)? {
+            PropValue::Int(i) => Some(*i as f32),
+            PropValue::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+    pub fn str(&self, key: &str) -> Option<&str> {
+        match self.get(key)? {
+            PropValue::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &PropValue)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v))
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+// frenderer's SheetRegion doesn't implement PartialEq, so compare it field by field
+pub fn same_region(a: &SheetRegion, b: &SheetRegion) -> bool {
+    (a.sheet, a.depth, a.x, a.y, a.w, a.h, a.colormod)
+        == (b.sheet, b.depth, b.x, b.y, b.w, b.h, b.colormod)
 }
 
 #[derive(Clone, Copy, Debug)]
 pub struct TileData {
-    pub solid: bool,
+    pub flags: TileFlags,
     pub sheet_region: SheetRegion,
 }
 
+impl PartialEq for TileData {
+    fn eq(&self, other: &Self) -> bool {
+        self.flags == other.flags && same_region(&self.sheet_region, &other.sheet_region)
+    }
+}
+
+// One grid of tiles drawn at its own depth.  Only collision layers are
+// returned by `tiles_within`, so decorations can go on layers of their own.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layer {
+    pub name: String,
+    pub depth: u16,
+    pub collision: bool,
+    pub grid: Grid<u8>,
+}
+
+impl Layer {
+    // The one layer a level file without any `layer` lines has
+    pub const MAIN: &'static str = "main";
+    pub const MAIN_DEPTH: u16 = 16;
+
+    pub fn main(grid: Grid<u8>) -> Self {
+        Self {
+            name: Self::MAIN.to_string(),
+            depth: Self::MAIN_DEPTH,
+            collision: true,
+            grid,
+        }
+    }
+    fn is_default(&self) -> bool {
+        self.name == Self::MAIN && self.depth == Self::MAIN_DEPTH && self.collision
+    }
+}
+
 #[allow(dead_code)]
+#[derive(Clone, Debug)]
 pub struct Level {
     name: String,
     bg: SheetRegion,
-    grid: Grid<u8>,
+    // drawn in order, all the same size
+    layers: Vec<Layer>,
     tileset: Tileset,
-    starts: Vec<(EntityType, Vec2)>,
+    starts: Vec<(EntityType, Vec2, Properties)>,
+    // seconds since the level was loaded; every animated tile plays on it
+    tile_t: f32,
 }
-#[allow(unused)]
-impl Level {
-    /*
-    We'll read from an ad hoc format like this, where FLAGS is either S (solid) or O (open) but could be other stuff later:
-
-    LEVELNAME W H
-    ====
-    SYM FLAGS X Y W H
-    SYM FLAGS X Y W H
-    SYM FLAGS X Y W H
-    ====
-    SYM SYM SYM SYM SYM
-    SYM SYM SYM SYM SYM
-    SYM SYM SYM SYM SYM
-    SYM SYM SYM SYM SYM
-    SYM SYM SYM SYM SYM
-    ====
-    player X Y
-    enemy X Y
-    enemy X Y
-    enemy X Y
-    Obstacle LEVELNAME TO-X TO-Y X Y
-    you can add more types of thing if you want
-    */
-    pub fn from_str(s: &str) -> Self {
-        enum State {
-            Metadata,
-            Legend,
-            Map,
-            Starts,
-            Done,
-        }
-        impl State {
-            fn next(self) -> Self {
-                match self {
-                    Self::Metadata => Self::Legend,
-                    Self::Legend => Self::Map,
-                    Self::Map => Self::Starts,
-                    Self::Starts => Self::Done,
-                    Self::Done => Self::Done,
-                }
+
+impl PartialEq for Level {
+    fn eq(&self, other: &Self) -> bool {
+        // destructured so that a new field can't be left out of the comparison
+        let Self {
+            name,
+            bg,
+            layers,
+            tileset,
+            starts,
+            tile_t,
+        } = self;
+        *name == other.name
+            && same_region(bg, &other.bg)
+            && *layers == other.layers
+            && *tileset == other.tileset
+            && *starts == other.starts
+            && *tile_t == other.tile_t
+    }
+}
+
+// The parts of a level file, in the order they appear
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Section {
+    Metadata,
+    Legend,
+    Map,
+    Starts,
+    Done,
+}
+
+impl Section {
+    fn next(self) -> Self {
+        match self {
+            Self::Metadata => Self::Legend,
+            Self::Legend => Self::Map,
+            Self::Map => Self::Starts,
+            Self::Starts => Self::Done,
+            Self::Done => Self::Done,
+        }
+    }
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Metadata => "metadata",
+            Self::Legend => "legend",
+            Self::Map => "map",
+            Self::Starts => "starts",
+            Self::Done => "end of file",
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LevelParseErrorKind {
+    // a line ended before this field
+    Missing(&'static str),
+    // this field wasn't a number that fits
+    BadNumber(&'static str),
+    DuplicateName,
+    DuplicateBg,
+    DuplicateSymbol,
+    DuplicateLayer,
+    UnknownLayerFlag,
+    TooManySymbols,
+    UnknownFlags,
+    UnknownRepeatMode,
+    // an animated tile's extra frame wasn't X,Y,TIME
+    BadFrame,
+    UnknownSymbol,
+    UnknownEntity,
+    RowLength { expected: usize, found: usize },
+    MapHeight { expected: usize, found: usize },
+    OutsideMap,
+    // a start line's extra token wasn't key=value
+    BadProperty,
+    DuplicateProperty,
+    NoName,
+    EmptyLegend,
+    TrailingContent,
+}
+
+/// Where and why a level file couldn't be read.  Lines and columns count from
+/// 1, and `token` is the offending bit of text (empty if something was missing).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LevelParseError {
+    pub file: Option<String>,
+    pub section: Section,
+    pub line: usize,
+    pub column: usize,
+    pub token: String,
+    pub kind: LevelParseErrorKind,
+}
+
+// Start lines give grid columns and rows counted from the top of the map;
+// starts are kept as the world position of that tile's center.
+pub fn start_to_world(x: u16, y: u16, height: u16) -> Vec2 {
+    Vec2 {
+        x: (x as usize * TILE_SZ) as f32 + TILE_SZ as f32 / 2.0,
+        y: ((height - y) as usize * TILE_SZ) as f32 - TILE_SZ as f32 / 2.0,
+    }
+}
+
+pub fn world_to_start(pos: Vec2, height: u16) -> (u16, u16) {
+    let x = (pos.x - TILE_SZ as f32 / 2.0) / TILE_SZ as f32;
+    let y = height as f32 - (pos.y + TILE_SZ as f32 / 2.0) / TILE_SZ as f32;
+    (x.round() as u16, y.round() as u16)
+}
+
+impl LevelParseError {
+    // Names the file in the error message, e.g. "level1.txt"
+    pub fn in_file(self, file: &str) -> Self {
+        Self {
+            file: Some(file.to_string()),
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for LevelParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use LevelParseErrorKind::*;
+        match &self.file {
+            Some(file) => write!(f, "{file}:{}: ", self.line)?,
+            None => write!(f, "line {}: ", self.line)?,
+        }
+        let token = &self.token;
+        match &self.kind {
+            Missing(what) => write!(f, "missing {what} in {} line", self.section),
+            BadNumber(what) => write!(f, "couldn't read {what} from '{token}'"),
+            DuplicateName => write!(f, "second level name '{token}'"),
+            DuplicateBg => write!(f, "second bg entry"),
+            DuplicateSymbol => write!(f, "symbol '{token}' is already in the legend"),
+            TooManySymbols => write!(f, "too many legend symbols (at most 256)"),
+            UnknownFlags => write!(
+                f,
+                "unknown tile flags '{token}' (expected o, s or a list like s,bouncy)"
+            ),
+            UnknownRepeatMode => write!(
+                f,
+                "unknown repeat mode '{token}' (expected loop, pingpong or once)"
+            ),
+            BadFrame => write!(f, "expected a frame X,Y,TIME, not '{token}'"),
+            DuplicateLayer => write!(f, "layer '{token}' is already in the map"),
+            UnknownLayerFlag => {
+                write!(f, "unknown layer flag '{token}' (expected collision)")
+            }
+            UnknownSymbol => write!(f, "unknown legend symbol '{token}'"),
+            UnknownEntity => write!(f, "unknown entity type '{token}'"),
+            RowLength { expected, found } => write!(
+                f,
+                "map row has {found} tiles but the level is {expected} wide"
+            ),
+            MapHeight { expected, found } if token.is_empty() => {
+                write!(f, "map has {found} rows but the level is {expected} tall")
             }
+            MapHeight { expected, found } => write!(
+                f,
+                "layer '{token}' has {found} rows but the level is {expected} tall"
+            ),
+            OutsideMap => write!(f, "y {token} is outside the map"),
+            BadProperty => write!(f, "expected a key=value property, not '{token}'"),
+            DuplicateProperty => write!(f, "property '{token}' sets a key that's already set"),
+            NoName => write!(f, "no level name and size before the {}", self.section),
+            EmptyLegend => write!(f, "the legend is empty"),
+            TrailingContent => write!(f, "unexpected '{token}' after the starts"),
+        }
+    }
+}
+
+impl std::error::Error for LevelParseError {}
+
+// The whitespace-separated words of one line, remembering where they were so
+// errors can point at them
+struct Tokens<'a> {
+    section: Section,
+    line_no: usize,
+    line: &'a str,
+    pos: usize,
+    // where the last token we handed out starts
+    last: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(section: Section, line_no: usize, line: &'a str) -> Self {
+        Self {
+            section,
+            line_no,
+            line,
+            pos: 0,
+            last: 0,
         }
-        let mut state = State::Metadata;
+    }
+    fn error_at(&self, pos: usize, token: &str, kind: LevelParseErrorKind) -> LevelParseError {
+        LevelParseError {
+            file: None,
+            section: self.section,
+            line: self.line_no,
+            column: self.line[..pos].chars().count() + 1,
+            token: token.to_string(),
+            kind,
+        }
+    }
+    // An error about the last token we handed out
+    fn error(&self, kind: LevelParseErrorKind) -> LevelParseError {
+        self.error_at(self.last, &self.line[self.last..self.pos], kind)
+    }
+    fn next_token(&mut self) -> Option<&'a str> {
+        let rest = &self.line[self.pos..];
+        let start = self.pos + (rest.len() - rest.trim_start().len());
+        let rest = &self.line[start..];
+        let len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        self.last = start;
+        self.pos = start + len;
+        (len > 0).then(|| &rest[..len])
+    }
+    // Like next_token, but whitespace between double quotes (as in
+    // `look="far left"`) doesn't end the token
+    fn quoted_token(&mut self) -> Option<&'a str> {
+        let rest = &self.line[self.pos..];
+        let start = self.pos + (rest.len() - rest.trim_start().len());
+        let rest = &self.line[start..];
+        let (mut quoted, mut escaped) = (false, false);
+        let len = rest
+            .find(|c: char| {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' if quoted => escaped = true,
+                    '"' => quoted = !quoted,
+                    c if c.is_whitespace() => return !quoted,
+                    _ => {}
+                }
+                false
+            })
+            .unwrap_or(rest.len());
+        self.last = start;
+        self.pos = start + len;
+        (len > 0).then(|| &rest[..len])
+    }
+    fn word(&mut self, what: &'static str) -> Result<&'a str, LevelParseError> {
+        self.next_token()
+            .ok_or_else(|| self.error_at(self.line.len(), "", LevelParseErrorKind::Missing(what)))
+    }
+    fn number<T: FromStr>(&mut self, what: &'static str) -> Result<T, LevelParseError> {
+        self.word(what)?
+            .parse()
+            .map_err(|_| self.error(LevelParseErrorKind::BadNumber(what)))
+    }
+}
+
+/*
+We'll read from an ad hoc format like this, where FLAGS is O (open), S (solid) or a list of
+tile flags (see `TileFlags`):
+
+LEVELNAME W H
+====
+SYM FLAGS X Y W H
+SYM FLAGS X Y W H
+SYM FLAGS X Y W H
+====
+SYM SYM SYM SYM SYM
+SYM SYM SYM SYM SYM
+SYM SYM SYM SYM SYM
+SYM SYM SYM SYM SYM
+SYM SYM SYM SYM SYM
+====
+player X Y
+enemy X Y
+enemy X Y
+enemy X Y
+obstacle LEVELNAME TO-X TO-Y X Y
+mud X Y
+you can add more types of thing if you want
+
+A legend line can go on to animate its tile, with a repeat mode (loop,
+pingpong or once), how long the X Y frame shows, and more frames of the same
+size as X,Y,TIME.  All animated tiles share the level's clock:
+
+wtr o 0 64 16 16 loop 0.25 16,64,0.25 32,64,0.5
+
+Any start line can end with key=value properties for that one entity, e.g.
+`enemy 4 5 speed=40 range=3.5 look="far left"`; see `PropValue`.
+
+The map can also be split into layers, each a full W by H grid drawn at its
+own depth (bigger is further back), all in front of the bg.  Only layers
+marked collision are solid; a map without layer lines is one layer called
+main at depth 16 with collision.
+Tiles with an empty sheet region (e.g. `none o 0 0 0 0`) aren't drawn, so
+layers can leave gaps:
+
+====
+layer background 18
+SYM SYM SYM SYM SYM
+...
+layer main 16 collision
+SYM SYM SYM SYM SYM
+...
+====
+*/
+impl FromStr for Level {
+    type Err = LevelParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use LevelParseErrorKind::*;
+        let mut section = Section::Metadata;
         let mut name = None;
-        let mut dims = None;
-        let mut legend: HashMap<String, (u8, TileData)> = std::collections::HashMap::new();
-        let mut grid = vec![];
+        let mut dims: Option<(u16, u16)> = None;
+        let mut legend: HashMap<String, (u8, TileData, Option<Animation>)> =
+            std::collections::HashMap::new();
+        // name, depth, collision, tiles, and whether it had a `layer` line
+        let mut layers: Vec<(String, u16, bool, Vec<u8>, bool)> = vec![];
         let mut starts = vec![];
         let mut bg = SheetRegion::ZERO;
-        for line in s.lines() {
-            if line.is_empty() {
+        let mut line_count = 0;
+        for (line_no, line) in s.lines().enumerate() {
+            let line_no = line_no + 1;
+            line_count = line_no;
+            if line.trim().is_empty() {
                 continue;
             } else if line.chars().all(|c| c == '=') {
-                state = state.next();
-            } else {
-                match state {
-                    State::Metadata => {
-                        let mut chunks = line.split_whitespace();
-                        let md = chunks
-                            .next()
-                            .expect("No metadata decl string in metadata line {line}");
-                        if md == "bg" {
-                            if bg.w != 0 {
-                                panic!("Two bg entries in metadata");
+                section = section.next();
+                continue;
+            }
+            let mut chunks = Tokens::new(section, line_no, line);
+            // everything past the metadata needs to know the level size
+            let (w, h) = match (section, dims) {
+                (Section::Metadata, _) => (0, 0),
+                (_, Some(dims)) => dims,
+                (_, None) => return Err(chunks.error_at(0, "", NoName)),
+            };
+            match section {
+                Section::Metadata => {
+                    let md = chunks.word("level name")?;
+                    if md == "bg" {
+                        if bg.w != 0 {
+                            return Err(chunks.error(DuplicateBg));
+                        }
+                        bg = SheetRegion::rect(
+                            chunks.number("bg x")?,
+                            chunks.number("bg y")?,
+                            chunks.number("bg width")?,
+                            chunks.number("bg height")?,
+                        );
+                    } else {
+                        if name.is_some() {
+                            return Err(chunks.error(DuplicateName));
+                        }
+                        name = Some(md.to_string());
+                        dims = Some((chunks.number("width")?, chunks.number("height")?));
+                    }
+                }
+                Section::Legend => {
+                    let sym = chunks.word("tile symbol")?;
+                    if legend.contains_key(sym) {
+                        return Err(chunks.error(DuplicateSymbol));
+                    }
+                    if legend.len() > u8::MAX as usize {
+                        return Err(chunks.error(TooManySymbols));
+                    }
+                    let flags = chunks
+                        .word("tile flags")?
+                        .parse()
+                        .map_err(|kind| chunks.error(kind))?;
+                    let x = chunks.number("sheet x")?;
+                    let y = chunks.number("sheet y")?;
+                    let w = chunks.number("sheet w")?;
+                    let h = chunks.number("sheet h")?;
+                    let data = TileData {
+                        flags,
+                        sheet_region: SheetRegion::new(0, x, y, 16, w, h),
+                    };
+                    let anim = match chunks.next_token() {
+                        None => None,
+                        Some(mode) => {
+                            let mode: RepeatMode =
+                                mode.parse().map_err(|()| chunks.error(UnknownRepeatMode))?;
+                            let t: f32 = chunks.number("frame time")?;
+                            if t.is_nan() || t <= 0.0 {
+                                return Err(chunks.error(BadNumber("frame time")));
                             }
-                            bg = SheetRegion::rect(
-                                u16::from_str(chunks.next().expect("No x in metadata line {line}"))
-                                    .expect("Couldn't parse x as u16 in {line}"),
-                                u16::from_str(chunks.next().expect("No y in metadata line {line}"))
-                                    .expect("Couldn't parse y as u16 in {line}"),
-                                i16::from_str(
-                                    chunks.next().expect("No width in metadata line {line}"),
-                                )
-                                .expect("Couldn't parse width as i16 in {line}"),
-                                i16::from_str(
-                                    chunks.next().expect("No height in metadata line {line}"),
-                                )
-                                .expect("Couldn't parse height as i16 in {line}"),
-                            )
-                            .with_depth(17);
-                        } else {
-                            if name.is_some() {
-                                panic!("Two name entries in metadata");
+                            let mut frames = vec![data.sheet_region];
+                            let mut timings = vec![t];
+                            while let Some(frame) = chunks.next_token() {
+                                let (fx, fy, ft) =
+                                    parse_frame(frame).ok_or_else(|| chunks.error(BadFrame))?;
+                                frames.push(SheetRegion::new(0, fx, fy, 16, w, h));
+                                timings.push(ft);
                             }
-                            name = Some(md.to_string());
-                            dims = Some((
-                                u16::from_str(
-                                    chunks.next().expect("No width in metadata line {line}"),
-                                )
-                                .expect("Couldn't parse width as u16 in {line}"),
-                                u16::from_str(
-                                    chunks.next().expect("No height in metadata line {line}"),
-                                )
-                                .expect("Couldn't parse height as u16 in {line}"),
-                            ));
+                            Some(
+                                Animation::with_frames_timings(&frames, timings)
+                                    .with_repeat_mode(mode),
+                            )
                         }
+                    };
+                    legend.insert(sym.to_string(), (legend.len() as u8, data, anim));
+                }
+                Section::Map if line.split_whitespace().next() == Some("layer") => {
+                    chunks.word("layer")?;
+                    let lname = chunks.word("layer name")?;
+                    if layers.iter().any(|l| l.0 == lname) {
+                        return Err(chunks.error(DuplicateLayer));
+                    }
+                    let depth = chunks.number("layer depth")?;
+                    let collision = match chunks.next_token() {
+                        None => false,
+                        Some("collision") => true,
+                        Some(_) => return Err(chunks.error(UnknownLayerFlag)),
+                    };
+                    layers.push((lname.to_string(), depth, collision, vec![], true));
+                }
+                Section::Map => {
+                    if layers.is_empty() {
+                        let main = Layer::MAIN.to_string();
+                        layers.push((main, Layer::MAIN_DEPTH, true, vec![], false));
                     }
-                    State::Legend => {
-                        let mut chunks = line.split_whitespace();
-                        let sym = chunks.next().expect("Couldn't get tile symbol in {line}");
-                        assert!(!legend.contains_key(sym), "Symbol {sym} already in legend");
-                        let flags = chunks
-                            .next()
-                            .expect("Couldn't get tile flags in {line}")
-                            .to_lowercase();
-                        assert!(flags == "o" || flags == "s", "The only valid flags are o(pen) or s(olid), but you could parse other kinds here in {line}");
-                        let x =
-                            u16::from_str(chunks.next().expect("No sheet x in legend line {line}"))
-                                .expect("Couldn't parse sheet x as u16 in {line}");
-                        let y =
-                            u16::from_str(chunks.next().expect("No sheet y in legend line {line}"))
-                                .expect("Couldn't parse sheet y as u16 in {line}");
-                        let w =
-                            i16::from_str(chunks.next().expect("No sheet w in legend line {line}"))
-                                .expect("Couldn't parse sheet w as i16 in {line}");
-                        let h =
-                            i16::from_str(chunks.next().expect("No sheet h in legend line {line}"))
-                                .expect("Couldn't parse sheet h as i16 in {line}");
-                        let data = TileData {
-                            solid: flags == "s",
-                            sheet_region: SheetRegion::new(0, x, y, 16, w, h),
+                    let grid = &mut layers.last_mut().unwrap().3;
+                    let mut found = 0;
+                    while let Some(sym) = chunks.next_token() {
+                        let Some((idx, _, _)) = legend.get(sym) else {
+                            return Err(chunks.error(UnknownSymbol));
                         };
-                        legend.insert(sym.to_string(), (legend.len() as u8, data));
+                        grid.push(*idx);
+                        found += 1;
                     }
-                    State::Map => {
-                        let old_len = grid.len();
-                        grid.extend(line.split_whitespace().map(|sym| legend[sym].0));
-                        assert_eq!(
-                            old_len + dims.unwrap().0 as usize,
-                            grid.len(),
-                            "map line is too short or too long: {line} for map dims {dims:?}"
-                        );
+                    if found != w as usize {
+                        let expected = w as usize;
+                        return Err(chunks.error_at(0, "", RowLength { expected, found }));
                     }
-                    State::Starts => {
-                        let mut chunks = line.split_whitespace();
-                        let etype = chunks
-                            .next()
-                            .expect("Couldn't get entity start type {line}");
-                        let etype = match etype {
-                            "player" => EntityType::Player,
-                            // "enemy" => EntityType::Enemy,
-                            "obstacle" => {
-                                let to_room = chunks.next().expect("Couldn't get dest room {line}");
-                                let to_x = u16::from_str(
-                                    chunks
-                                        .next()
-                                        .expect("No dest x coord in obstacle line {line}"),
-                                )
-                                .expect("Couldn't parse x coord as u16 in {line}");
-                                let to_y = u16::from_str(
-                                    chunks
-                                        .next()
-                                        .expect("No dest y coord in obstacle line {line}"),
-                                )
-                                .expect("Couldn't parse y coord as u16 in {line}");
-                                EntityType::Obstacle(to_room.to_string(), to_x, to_y)
-                            }
-                            _ => panic!("Unrecognized entity type in {line}"),
-                        };
-                        let x =
-                            u16::from_str(chunks.next().expect("No x coord in start line {line}"))
-                                .expect("Couldn't parse x coord as u16 in {line}");
-                        let y =
-                            u16::from_str(chunks.next().expect("No y coord in start line {line}"))
-                                .expect("Couldn't parse y coord as u16 in {line}");
-                        starts.push((
-                            etype,
-                            Vec2 {
-                                x: (x as usize * TILE_SZ) as f32 + TILE_SZ as f32 / 2.0,
-                                y: ((dims.unwrap().1 - y) as usize * TILE_SZ) as f32
-                                    - TILE_SZ as f32 / 2.0,
-                            },
-                        ));
+                }
+                Section::Starts => {
+                    let etype = chunks.word("entity type")?;
+                    let etype = match etype {
+                        "player" => EntityType::Player,
+                        "enemy" => EntityType::Enemy,
+                        "obstacle" => {
+                            let to_room = chunks.word("destination level")?;
+                            let to_x = chunks.number("destination x")?;
+                            let to_y = chunks.number("destination y")?;
+                            EntityType::Obstacle(to_room.to_string(), to_x, to_y)
+                        }
+                        "mud" => EntityType::Mud,
+                        _ => return Err(chunks.error(UnknownEntity)),
+                    };
+                    let x: u16 = chunks.number("x")?;
+                    let y: u16 = chunks.number("y")?;
+                    if y > h {
+                        return Err(chunks.error(OutsideMap));
                     }
-                    State::Done => {
-                        panic!("Unexpected file content after parsing finished in {line}")
+                    let mut props = Properties::default();
+                    while let Some(token) = chunks.quoted_token() {
+                        let Some((key, value)) =
+                            token.split_once('=').filter(|(key, _)| !key.is_empty())
+                        else {
+                            return Err(chunks.error(BadProperty));
+                        };
+                        let Ok(value) = value.parse();
+                        if props.insert(key, value).is_some() {
+                            return Err(chunks.error(DuplicateProperty));
+                        }
                     }
+                    starts.push((etype, start_to_world(x, y, h), props));
+                }
+                Section::Done => {
+                    chunks.word("content")?;
+                    return Err(chunks.error(TrailingContent));
                 }
             }
         }
-        assert_ne!(name, None);
-        let name = name.unwrap();
-        assert_ne!(dims, None);
-        let (w, h) = dims.unwrap();
-        assert!(!legend.is_empty());
-        assert_eq!(grid.len(), w as usize * h as usize);
-        let mut tiles: Vec<(u8, TileData)> = legend.into_values().collect();
-        tiles.sort_by_key(|(num, _)| *num);
-        Self {
+        // Problems with the file as a whole are reported at its last line
+        let at_end = |section, kind| LevelParseError {
+            file: None,
+            section,
+            line: line_count,
+            column: 1,
+            token: String::new(),
+            kind,
+        };
+        let (Some(name), Some((w, h))) = (name, dims) else {
+            return Err(at_end(section, NoName));
+        };
+        if legend.is_empty() {
+            return Err(at_end(Section::Legend, EmptyLegend));
+        }
+        if layers.is_empty() {
+            let expected = h as usize;
+            return Err(at_end(Section::Map, MapHeight { expected, found: 0 }));
+        }
+        for (lname, _, _, grid, named) in layers.iter() {
+            if grid.len() != w as usize * h as usize {
+                let expected = h as usize;
+                let found = grid.len() / (w as usize).max(1);
+                let mut err = at_end(Section::Map, MapHeight { expected, found });
+                if *named {
+                    err.token = lname.clone();
+                }
+                return Err(err);
+            }
+        }
+        let mut tiles: Vec<(u8, String, TileData, Option<Animation>)> = legend
+            .into_iter()
+            .map(|(sym, (num, data, anim))| (num, sym, data, anim))
+            .collect();
+        tiles.sort_by_key(|(num, _, _, _)| *num);
+        Ok(Self {
             bg,
             name: name.to_string(),
-            grid: Grid::new(w as usize, h as usize, grid),
+            layers: layers
+                .into_iter()
+                .map(|(name, depth, collision, grid, _)| Layer {
+                    name,
+                    depth,
+                    collision,
+                    grid: Grid::new(w as usize, h as usize, grid),
+                })
+                .collect(),
+            tileset: Tileset {
+                symbols: tiles.iter().map(|(_num, sym, _, _)| sym.clone()).collect(),
+                anims: tiles
+                    .iter()
+                    .map(|(_num, _, _, anim)| anim.clone())
+                    .collect(),
+                tiles: tiles.into_iter().map(|(_num, _, val, _)| val).collect(),
+            },
+            starts,
+            tile_t: 0.0,
+        })
+    }
+}
+
+#[allow(unused)]
+impl Level {
+    // For tools and importers that build levels without going through the
+    // text format.  `tiles` is the legend, in order, and the grid holds
+    // indices into it.
+    pub fn new(
+        name: &str,
+        bg: SheetRegion,
+        grid: Grid<u8>,
+        tiles: Vec<(String, TileData)>,
+        starts: Vec<(EntityType, Vec2, Properties)>,
+    ) -> Self {
+        Self::with_layers(name, bg, vec![Layer::main(grid)], tiles, starts)
+    }
+    // Like `new`, but with any number of layers (at least one, all the same size)
+    pub fn with_layers(
+        name: &str,
+        bg: SheetRegion,
+        layers: Vec<Layer>,
+        tiles: Vec<(String, TileData)>,
+        starts: Vec<(EntityType, Vec2, Properties)>,
+    ) -> Self {
+        assert!(!layers.is_empty(), "A level needs at least one layer");
+        let (w, h) = (layers[0].grid.width(), layers[0].grid.height());
+        assert!(
+            layers
+                .iter()
+                .all(|l| l.grid.width() == w && l.grid.height() == h),
+            "All of a level's layers have to be the same size"
+        );
+        let (symbols, tiles): (Vec<_>, Vec<_>) = tiles.into_iter().unzip();
+        Self {
+            name: name.to_string(),
+            bg,
+            layers,
             tileset: Tileset {
-                tiles: tiles.into_iter().map(|(_num, val)| val).collect(),
+                anims: vec![None; tiles.len()],
+                tiles,
+                symbols,
             },
             starts,
+            tile_t: 0.0,
         }
     }
+    // Loads `content/{name}.txt`.  A missing file is a bug in the game, but a
+    // file that doesn't parse is something to show the level designer.
+    pub fn load(cache: &AssetCache, name: &str) -> Result<Self, LevelParseError> {
+        cache
+            .load::<String>(name)
+            .unwrap_or_else(|e| panic!("Couldn't access {name}.txt: {e}"))
+            .read()
+            .parse()
+            .map_err(|e: LevelParseError| e.in_file(&format!("{name}.txt")))
+    }
+    // Advances the clock animated tiles play on
+    pub fn tick(&mut self, dt: f32) {
+        self.tile_t += dt;
+    }
+    pub fn tile_animation(&self, tile: u8) -> Option<&Animation> {
+        self.tileset.anims[tile as usize].as_ref()
+    }
     pub fn sprite_count(&self) -> usize {
-        self.grid.width() * self.grid.height() + 1
+        self.layers.len() * self.width() * self.height() + 1
     }
+    // How many sprites render_visible_into needs for this camera
+    pub fn visible_sprite_count(&self, camera: &Camera2D) -> usize {
+        let (xs, ys) = self.visible_tiles(camera);
+        self.layers.len() * xs.len() * ys.len() + 1
+    }
+    // The grid columns and rows the camera can see, with VISIBLE_MARGIN
+    // extra tiles around them so scrolling doesn't show a gap before the next
+    // frame.  Rows count down from the top, like grid coordinates.
+    pub fn visible_tiles(&self, camera: &Camera2D) -> (Range<usize>, Range<usize>) {
+        let tiles = |from: f32, to: f32, len: usize| {
+            let start = (from / TILE_SZ as f32).floor() as i64 - VISIBLE_MARGIN as i64;
+            let end = (to / TILE_SZ as f32).ceil() as i64 + VISIBLE_MARGIN as i64;
+            let clamp = |t: i64| t.clamp(0, len as i64) as usize;
+            clamp(start)..clamp(end)
+        };
+        let [x, y] = camera.screen_pos;
+        let [w, h] = camera.screen_size;
+        let xs = tiles(x, x + w, self.width());
+        // world y goes up from the bottom of the level
+        let up = tiles(y, y + h, self.height());
+        let ys = (self.height() - up.end)..(self.height() - up.start);
+        (xs, ys)
+    }
+    // Draws every layer in order, then the background
     pub fn render_into(&self, frend: &mut Renderer, offset: usize) -> usize {
-        let len = self.sprite_count();
-        let h = self.grid.height();
-        let (trfs, uvs) = frend.sprites_mut(0, offset..len);
+        self.render_tiles(frend, offset, 0..self.width(), 0..self.height())
+    }
+    // Like render_into, but only the tiles near the camera, so long levels
+    // cost no more to draw than short ones.  Size the sprite group with
+    // visible_sprite_count.
+    pub fn render_visible_into(
+        &self,
+        frend: &mut Renderer,
+        offset: usize,
+        camera: &Camera2D,
+    ) -> usize {
+        let (xs, ys) = self.visible_tiles(camera);
+        self.render_tiles(frend, offset, xs, ys)
+    }
+    fn render_tiles(
+        &self,
+        frend: &mut Renderer,
+        offset: usize,
+        xs: Range<usize>,
+        ys: Range<usize>,
+    ) -> usize {
+        let len = self.layers.len() * xs.len() * ys.len() + 1;
+        let h = self.height();
+        let (trfs, uvs) = frend.sprites_mut(0, offset..offset + len);
         let mut trfs = trfs.iter_mut();
         let mut uvs = uvs.iter_mut();
-        for (y, row) in self.grid.row_iter().enumerate() {
-            for (x, tile) in row.iter().enumerate() {
-                let trf = trfs.next().unwrap();
-                let uv = uvs.next().unwrap();
-                // NOTE: we're converting from grid coordinates to "sprite center coordinates", so we have to flip y...
-                let y = h - y - 1;
-                *trf = Transform {
-                    // and multiply by tile sz
-                    x: (x * TILE_SZ + TILE_SZ / 2) as f32,
-                    y: (y * TILE_SZ + TILE_SZ / 2) as f32,
-                    w: TILE_SZ as u16,
-                    h: TILE_SZ as u16,
-                    rot: 0.0,
-                };
-                *uv = self.tileset[*tile as usize].sheet_region;
+        for layer in self.layers.iter() {
+            for y in ys.clone() {
+                for x in xs.clone() {
+                    let trf = trfs.next().unwrap();
+                    let uv = uvs.next().unwrap();
+                    let tile = layer.grid.get(x, y).unwrap();
+                    let region = self.tileset.region(*tile, self.tile_t);
+                    // tiles with an empty region (like `none o 0 0 0 0`) get a zero-size quad so they draw nothing
+                    let sz = if region.w == 0 || region.h == 0 {
+                        0
+                    } else {
+                        TILE_SZ as u16
+                    };
+                    // NOTE: we're converting from grid coordinates to "sprite center coordinates", so we have to flip y...
+                    let y = h - y - 1;
+                    *trf = Transform {
+                        // and multiply by tile sz
+                        x: (x * TILE_SZ + TILE_SZ / 2) as f32,
+                        y: (y * TILE_SZ + TILE_SZ / 2) as f32,
+                        w: sz,
+                        h: sz,
+                        rot: 0.0,
+                    };
+                    *uv = region.with_depth(layer.depth);
+                }
             }
         }
-        *trfs.next().unwrap() = Transform {
-            x: (self.grid.width() * TILE_SZ) as f32 / 2.0,
-            y: (self.grid.height() * TILE_SZ) as f32 / 2.0,
-            w: (self.grid.width() as u16 * TILE_SZ as u16),
-            h: (self.grid.height() as u16 * TILE_SZ as u16),
-            rot: 0.0,
-        };
-        *uvs.next().unwrap() = self.bg;
+        *trfs.next().unwrap() = self.bg_transform(xs, ys);
+        *uvs.next().unwrap() = self.bg.with_depth(self.bg_depth());
         len
     }
+    // The background goes behind every layer, but not so far back that it's
+    // clipped
+    fn bg_depth(&self) -> u16 {
+        self.layers
+            .iter()
+            .map(|layer| layer.depth)
+            .max()
+            .unwrap_or(Layer::MAIN_DEPTH)
+            .saturating_add(1)
+            .min(u16::MAX - 1)
+    }
+    // The background goes behind just the tiles being drawn, since a whole
+    // level thousands of columns long is wider than a sprite can be
+    fn bg_transform(&self, xs: Range<usize>, ys: Range<usize>) -> Transform {
+        let size = |tiles: usize| (tiles * TILE_SZ).min(u16::MAX as usize) as u16;
+        // rows count down from the top, but world y goes up
+        let h = self.height();
+        Transform {
+            x: ((xs.start + xs.end) * TILE_SZ) as f32 / 2.0,
+            y: ((2 * h - ys.start - ys.end) * TILE_SZ) as f32 / 2.0,
+            w: size(xs.len()),
+            h: size(ys.len()),
+            rot: 0.0,
+        }
+    }
     #[allow(dead_code)]
     pub fn name(&self) -> &str {
         &self.name
     }
-    pub fn starts(&self) -> &[(EntityType, Vec2)] {
+    pub fn starts(&self) -> &[(EntityType, Vec2, Properties)] {
         &self.starts
     }
+    pub fn add_start(&mut self, etype: EntityType, pos: Vec2, props: Properties) {
+        self.starts.push((etype, pos, props));
+    }
+    pub fn retain_starts(&mut self, keep: impl FnMut(&(EntityType, Vec2, Properties)) -> bool) {
+        self.starts.retain(keep);
+    }
+    // Every layer's tiles in column x, top to bottom
+    pub fn column(&self, x: usize) -> Vec<Vec<u8>> {
+        self.layers
+            .iter()
+            .map(|l| {
+                (0..self.height())
+                    .map(|y| *l.grid.get(x, y).unwrap())
+                    .collect()
+            })
+            .collect()
+    }
+    // Drops the first n columns and adds n copies of `column` (as returned by
+    // `Level::column`) at the end.  Starts in the dropped columns go, and the
+    // rest move back with the map.
+    pub fn scroll_left(&mut self, n: usize, column: &[Vec<u8>]) {
+        for (layer, col) in self.layers.iter_mut().zip(column) {
+            layer.grid.scroll_left(n, |y| col[y]);
+        }
+        let dx = (n * TILE_SZ) as f32;
+        self.starts.retain(|(_, pos, _)| pos.x >= dx);
+        for (_, pos, _) in self.starts.iter_mut() {
+            pos.x -= dx;
+        }
+    }
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+    pub fn layer(&self, name: &str) -> Option<&Layer> {
+        self.layers.iter().find(|l| l.name == name)
+    }
+    fn collision_layers(&self) -> impl Iterator<Item = &Layer> {
+        self.layers.iter().filter(|l| l.collision)
+    }
+    // The legend entry a grid value refers to
+    pub fn tile_data(&self, tile: u8) -> &TileData {
+        &self.tileset[tile as usize]
+    }
+    // The grid value for a legend symbol
+    pub fn symbol_index(&self, sym: &str) -> Option<u8> {
+        self.tileset
+            .symbols
+            .iter()
+            .position(|s| s == sym)
+            .map(|i| i as u8)
+    }
+    // The legend symbol for a grid value
+    pub fn symbol(&self, tile: u8) -> &str {
+        &self.tileset.symbols[tile as usize]
+    }
+    // Changes one tile of one layer (counting layers in drawing order)
+    pub fn set_tile(&mut self, layer: usize, x: usize, y: usize, tile: u8) {
+        assert!(
+            (tile as usize) < self.tileset.tiles.len(),
+            "Tile {tile} isn't in the legend"
+        );
+        *self.layers[layer].grid.get_mut(x, y).unwrap() = tile;
+    }
+    // The tile at `pos` on the first collision layer
     pub fn get_tile_at(&self, pos: Vec2) -> Option<&TileData> {
         let (gx, gy) = self.world_to_grid(pos);
-        self.grid.get(gx, gy).map(|t| &self.tileset[*t as usize])
+        let layer = self.collision_layers().next()?;
+        layer.grid.get(gx, gy).map(|t| &self.tileset[*t as usize])
     }
     pub fn tile_index_at(&self, pos: Vec2) -> Option<usize> {
         let (gx, gy) = self.world_to_grid(pos);
-        self.grid.xy_to_index(gx, gy)
+        self.layers[0].grid.xy_to_index(gx, gy)
     }
     pub fn grid_to_world(&self, pos: grid::Coord) -> Vec2 {
         Vec2 {
             x: pos.0 as f32 * TILE_SZ as f32,
-            y: (self.grid.height() - pos.1 - 1) as f32 * TILE_SZ as f32,
+            y: (self.height() - pos.1 - 1) as f32 * TILE_SZ as f32,
         }
     }
     pub fn world_to_grid(&self, pos: Vec2) -> grid::Coord {
         (
             (pos.x / TILE_SZ as f32) as usize,
-            (((self.grid.height() as f32 * TILE_SZ as f32) - pos.y - 1.0) / TILE_SZ as f32)
-                as usize,
+            (((self.height() as f32 * TILE_SZ as f32) - pos.y - 1.0) / TILE_SZ as f32) as usize,
         )
     }
+    // Tiles on collision layers overlapping (or next to) `rect`.  The grid
+    // is already a spatial hash of TILE_SZ cells, so this just slices out
+    // the rows under `rect` and works out each tile's rect from its column.
     pub fn tiles_within(&self, rect: Rect) -> impl Iterator<Item = (Rect, &TileData)> {
-        let (l, t) = self.world_to_grid(Vec2 {
-            x: rect.x,
-            y: rect.y,
-        });
+        let (l, t) = self.world_to_grid(rect.origin());
         let (r, b) = self.world_to_grid(Vec2 {
             x: rect.x + rect.w as f32,
             y: rect.y + rect.h as f32,
         });
-        (b..(t + 2)).flat_map(move |row| {
-            (l..(r + 2)).filter_map(move |col| {
-                self.grid.get(col, row).map(|tile_dat| {
-                    let world = self.grid_to_world((col, row));
-                    (
-                        Rect {
-                            x: world.x,
-                            y: world.y,
-                            w: TILE_SZ as u16,
-                            h: TILE_SZ as u16,
-                        },
-                        &self.tileset[*tile_dat as usize],
-                    )
+        let (w, h) = (self.width(), self.height());
+        let cols = l.min(w)..(r + 2).min(w);
+        let rows = b.min(h)..(t + 2).min(h);
+        self.collision_layers().flat_map(move |layer| {
+            let cols = cols.clone();
+            rows.clone().flat_map(move |row| {
+                let y = ((h - row - 1) * TILE_SZ) as f32;
+                let cells = &layer.grid.row(row).unwrap_or(&[])[cols.clone()];
+                cells.iter().zip(cols.clone()).map(move |(tile, col)| {
+                    let rect = Rect {
+                        x: (col * TILE_SZ) as f32,
+                        y,
+                        w: TILE_SZ as u16,
+                        h: TILE_SZ as u16,
+                    };
+                    (rect, &self.tileset[*tile as usize])
                 })
             })
         })
     }
     pub fn width(&self) -> usize {
-        self.grid.width()
+        self.layers[0].grid.width()
     }
     pub fn height(&self) -> usize {
-        self.grid.height()
+        self.layers[0].grid.height()
     }
 }
 
-#[derive(Debug)]
+// Writes the level back out in the format `from_str` reads, so that parsing
+// the output gives back an equal level.  Legend symbols and map cells are
+// padded to line up like the hand-written files.
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (w, h) = (self.width(), self.height());
+        writeln!(f, "{} {w} {h}", self.name)?;
+        if !same_region(&self.bg, &SheetRegion::ZERO) {
+            let bg = self.bg;
+            writeln!(f, "bg {} {} {} {}", bg.x, bg.y, bg.w, bg.h)?;
+        }
+        writeln!(f, "====")?;
+        let sym_w = self
+            .tileset
+            .symbols
+            .iter()
+            .map(|s| s.len())
+            .max()
+            .unwrap_or(0);
+        for (i, (sym, tile)) in self
+            .tileset
+            .symbols
+            .iter()
+            .zip(self.tileset.tiles.iter())
+            .enumerate()
+        {
+            let r = tile.sheet_region;
+            let flags = tile.flags;
+            write!(f, "{sym:sym_w$} {flags} {} {} {} {}", r.x, r.y, r.w, r.h)?;
+            if let Some(anim) = &self.tileset.anims[i] {
+                write!(f, " {} {}", anim.repeat_mode(), anim.timings()[0])?;
+                for (frame, t) in anim.frames().iter().zip(anim.timings()).skip(1) {
+                    write!(f, " {},{},{t}", frame.x, frame.y)?;
+                }
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "====")?;
+        // a lone main layer is written without a layer line, like older files
+        let headers = !(self.layers.len() == 1 && self.layers[0].is_default());
+        for layer in self.layers.iter() {
+            if headers {
+                let collision = if layer.collision { " collision" } else { "" };
+                writeln!(f, "layer {} {}{collision}", layer.name, layer.depth)?;
+            }
+            for row in layer.grid.row_iter() {
+                let syms: Vec<_> = row
+                    .iter()
+                    .map(|t| format!("{:sym_w$}", self.tileset.symbols[*t as usize]))
+                    .collect();
+                writeln!(f, "{}", syms.join(" ").trim_end())?;
+            }
+        }
+        writeln!(f, "====")?;
+        for (etype, pos, props) in self.starts.iter() {
+            match etype {
+                EntityType::Player => write!(f, "player")?,
+                EntityType::Enemy => write!(f, "enemy")?,
+                EntityType::Obstacle(to, to_x, to_y) => write!(f, "obstacle {to} {to_x} {to_y}")?,
+                EntityType::Mud => write!(f, "mud")?,
+            }
+            let (x, y) = world_to_start(*pos, h as u16);
+            write!(f, " {x} {y}")?;
+            for (key, value) in props.iter() {
+                write!(f, " {key}={value}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 struct Tileset {
     tiles: Vec<TileData>,
+    // the legend symbol for each tile, in legend order
+    symbols: Vec<String>,
+    anims: Vec<Option<Animation>>,
+}
+impl Tileset {
+    // What to draw for a tile at time t.  Animations that don't repeat stay
+    // on their last frame.
+    fn region(&self, tile: u8, t: f32) -> SheetRegion {
+        match &self.anims[tile as usize] {
+            None => self.tiles[tile as usize].sheet_region,
+            Some(anim) => anim
+                .sample(t)
+                .unwrap_or_else(|| *anim.frames().last().unwrap()),
+        }
+    }
 }
 impl std::ops::Index<usize> for Tileset {
     type Output = TileData;
@@ -337,3 +1240,347 @@ impl std::ops::Index<usize> for Tileset {
         &self.tiles[index]
     }
 }
+
+// An animated tile's extra frame, X,Y,TIME
+fn parse_frame(frame: &str) -> Option<(u16, u16, f32)> {
+    let mut parts = frame.split(',');
+    let x = parts.next()?.parse().ok()?;
+    let y = parts.next()?.parse().ok()?;
+    let t: f32 = parts.next()?.parse().ok()?;
+    (parts.next().is_none() && t > 0.0).then_some((x, y, t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    const SMALL: &str = "small 3 2
+bg 0 0 2 2
+====
+air o 0 0 16 16
+gnd s 16 0 16 16
+====
+air air air
+gnd gnd gnd
+====
+player 0 1
+obstacle small 1 1 2 1
+";
+    #[test]
+    fn test_parse_level() {
+        let level: Level = SMALL.parse().unwrap();
+        assert_eq!(level.name(), "small");
+        assert_eq!((level.width(), level.height()), (3, 2));
+        assert_eq!(level.starts().len(), 2);
+        assert_eq!(
+            level.starts()[1],
+            (
+                EntityType::Obstacle("small".to_string(), 1, 1),
+                Vec2 { x: 40.0, y: 8.0 },
+                Properties::default()
+            )
+        );
+    }
+    #[test]
+    fn test_round_trip() {
+        for text in [SMALL, include_str!("../content/level1.txt")] {
+            let level: Level = text.parse().unwrap();
+            let written = level.to_string();
+            assert_eq!(written.parse::<Level>().unwrap(), level, "{written}");
+        }
+        let written = SMALL.parse::<Level>().unwrap().to_string();
+        assert!(written.starts_with("small 3 2\nbg 0 0 2 2\n====\nair o 0 0 16 16\ngnd s"));
+        assert!(written.ends_with("====\nplayer 0 1\nobstacle small 1 1 2 1\n"));
+    }
+    #[test]
+    fn test_level_eq_checks_bg() {
+        let level: Level = SMALL.parse().unwrap();
+        let mut other: Level = SMALL.parse().unwrap();
+        assert_eq!(level, other);
+        other.bg.w = 3;
+        assert_ne!(level, other);
+        other.bg = level.bg;
+        other.tileset.tiles[1].sheet_region.x = 32;
+        assert_ne!(level, other);
+    }
+    #[test]
+    fn test_visible_tiles() {
+        // 100 columns and 10 rows, with a 4x2 tile camera
+        let row = vec!["air"; 100].join(" ");
+        let rows = [row.as_str(); 10].join("\n");
+        let long: Level = format!(
+            "long 100 10\nbg 0 0 2 2\n====\nair o 0 0 16 16\n====\n{rows}\n====\nplayer 0 1\n"
+        )
+        .parse()
+        .unwrap();
+        let camera = |x: f32, y: f32| Camera2D {
+            screen_pos: [x, y],
+            screen_size: [64.0, 32.0],
+        };
+        // the bottom rows are the last ones in the grid
+        assert_eq!(long.visible_tiles(&camera(160.0, 0.0)), (8..16, 6..10));
+        assert_eq!(long.visible_sprite_count(&camera(160.0, 0.0)), 8 * 4 + 1);
+        // clamped at the edges of the level
+        assert_eq!(long.visible_tiles(&camera(0.0, 128.0)), (0..6, 0..4));
+        assert_eq!(long.visible_tiles(&camera(1590.0, 40.0)), (97..100, 3..10));
+        // a camera showing the whole level draws all of it
+        let all = Camera2D {
+            screen_pos: [0.0, 0.0],
+            screen_size: [1600.0, 160.0],
+        };
+        assert_eq!(long.visible_sprite_count(&all), long.sprite_count());
+    }
+    #[test]
+    fn test_visible_long_level() {
+        // wider than a sprite's u16 size can cover
+        let row = vec!["air"; 5000].join(" ");
+        let rows = [row.as_str(); 4].join("\n");
+        let long: Level = format!(
+            "long 5000 4\nbg 0 0 2 2\n====\nair o 0 0 16 16\n====\n{rows}\n====\nplayer 0 1\n"
+        )
+        .parse()
+        .unwrap();
+        let camera = Camera2D {
+            screen_pos: [70000.0, 0.0],
+            screen_size: [64.0, 64.0],
+        };
+        let (xs, ys) = long.visible_tiles(&camera);
+        assert_eq!((xs.clone(), ys.clone()), (4373..4381, 0..4));
+        assert_eq!(long.visible_sprite_count(&camera), 8 * 4 + 1);
+        // the background covers what's drawn
+        let bg = long.bg_transform(xs, ys);
+        assert_eq!((bg.x, bg.y, bg.w, bg.h), (70032.0, 32.0, 128, 64));
+        // and drawing the whole level doesn't overflow
+        let bg = long.bg_transform(0..5000, 0..4);
+        assert_eq!((bg.x, bg.w), (40000.0, u16::MAX));
+    }
+    #[test]
+    fn test_animated_tiles() {
+        let animated = SMALL.replace(
+            "air o 0 0 16 16\n",
+            "air o 0 0 16 16 pingpong 0.5 16,0,0.25 32,0,0.25\n",
+        );
+        let mut level: Level = animated.parse().unwrap();
+        let anim = level.tile_animation(0).unwrap();
+        assert_eq!(anim.repeat_mode(), RepeatMode::PingPong);
+        assert_eq!(anim.timings(), &[0.5, 0.25, 0.25]);
+        assert!(same_region(
+            &anim.frames()[2],
+            &SheetRegion::new(0, 32, 0, 16, 16, 16)
+        ));
+        assert!(level.tile_animation(1).is_none());
+        // every animated tile follows the level's clock
+        assert_eq!(level.tileset.region(0, level.tile_t).x, 0);
+        level.tick(0.6);
+        assert_eq!(level.tileset.region(0, level.tile_t).x, 16);
+        level.tick(0.3);
+        assert_eq!(level.tileset.region(0, level.tile_t).x, 32);
+        // and back again
+        level.tick(0.4);
+        assert_eq!(level.tileset.region(0, level.tile_t).x, 16);
+        // one-shot animations stay on their last frame
+        let once: Level = SMALL
+            .replace("air o 0 0 16 16\n", "air o 0 0 16 16 once 0.5 16,0,0.5\n")
+            .parse()
+            .unwrap();
+        assert_eq!(once.tileset.region(0, 5.0).x, 16);
+        // animations survive being written out
+        let written = animated.parse::<Level>().unwrap().to_string();
+        assert!(written.contains("air o 0 0 16 16 pingpong 0.5 16,0,0.25 32,0,0.25\n"));
+        assert_eq!(written.parse::<Level>().unwrap(), animated.parse().unwrap());
+
+        let err = |legend: &str| {
+            let err = SMALL
+                .replace("air o 0 0 16 16\n", legend)
+                .parse::<Level>()
+                .unwrap_err();
+            (err.column, err.to_string())
+        };
+        assert_eq!(
+            err("air o 0 0 16 16 bounce 0.5\n"),
+            (
+                17,
+                "line 4: unknown repeat mode 'bounce' (expected loop, pingpong or once)".into()
+            )
+        );
+        assert_eq!(
+            err("air o 0 0 16 16 loop 0.5 16,0\n"),
+            (26, "line 4: expected a frame X,Y,TIME, not '16,0'".into())
+        );
+        assert_eq!(
+            err("air o 0 0 16 16 loop 0\n"),
+            (22, "line 4: couldn't read frame time from '0'".into())
+        );
+    }
+    #[test]
+    fn test_layers() {
+        let layered = SMALL.replace(
+            "====\nair air air\n",
+            "====\nlayer sky 18\ngnd gnd gnd\ngnd gnd gnd\nlayer main 16 collision\nair air air\n",
+        );
+        let level: Level = layered.parse().unwrap();
+        assert_eq!(level.layers().len(), 2);
+        assert_eq!(level.layer("sky").unwrap().depth, 18);
+        // the bg is drawn behind every layer, even one further back than main
+        assert!(level.layers().iter().all(|l| l.depth < level.bg_depth()));
+        assert_eq!(SMALL.parse::<Level>().unwrap().bg_depth(), 17);
+        assert_eq!(level.sprite_count(), 2 * 3 * 2 + 1);
+        // the sky layer is all solid tiles, but it isn't a collision layer
+        let small: Level = SMALL.parse().unwrap();
+        let row = Rect {
+            x: 0.0,
+            y: 16.0,
+            w: 48,
+            h: 16,
+        };
+        let solid = |level: &Level| {
+            level
+                .tiles_within(row)
+                .filter(|(_, td)| td.flags.solid)
+                .count()
+        };
+        assert_eq!(solid(&level), solid(&small));
+        assert_eq!(level.to_string().parse::<Level>().unwrap(), level);
+        // a lone main layer doesn't need a layer line
+        assert!(!small.to_string().contains("\nlayer "));
+
+        let err = parse_err(&layered.replace("sky 18", "main 18"));
+        assert_eq!(
+            err.to_string(),
+            "line 10: layer 'main' is already in the map"
+        );
+        let err = parse_err(&layered.replace("gnd gnd gnd\nlayer", "layer"));
+        assert_eq!(
+            err.to_string(),
+            "line 14: layer 'sky' has 1 rows but the level is 2 tall"
+        );
+    }
+    #[test]
+    fn test_tile_flags() {
+        let flagged = SMALL.replace(
+            "gnd s 16 0 16 16\n",
+            "gnd s 16 0 16 16\nmud slow=0.5 0 16 16 16\nspk s,hazard 16 16 16 16\nplt oneway,bouncy 0 32 16 16\nend GOAL 16 32 16 16\n",
+        );
+        let level: Level = flagged.parse().unwrap();
+        let flags: Vec<TileFlags> = level.tileset.tiles.iter().map(|t| t.flags).collect();
+        assert_eq!(flags[0], TileFlags::OPEN);
+        assert_eq!(flags[1], TileFlags::SOLID);
+        assert_eq!(flags[2].slow, Some(0.5));
+        assert!(flags[3].solid && flags[3].hazard && !flags[3].goal);
+        assert!(flags[4].one_way && flags[4].bouncy && !flags[4].solid);
+        assert!(flags[5].goal);
+        assert_eq!(flags[2].union(flags[3]).slow, Some(0.5));
+        assert!(flags[2].union(flags[3]).hazard);
+        // flags are written back out the way they're read
+        let written = level.to_string();
+        assert!(written.contains("spk s,hazard 16 16 16 16\n"));
+        assert!(written.contains("end goal 16 32 16 16\n"));
+        assert_eq!(written.parse::<Level>().unwrap(), level);
+
+        let err = parse_err(&SMALL.replace("gnd s", "gnd s,sticky"));
+        assert_eq!(err.kind, LevelParseErrorKind::UnknownFlags);
+        assert_eq!(
+            (err.line, err.column, err.token.as_str()),
+            (5, 5, "s,sticky")
+        );
+        let err = parse_err(&SMALL.replace("gnd s", "gnd o,s"));
+        assert_eq!(err.kind, LevelParseErrorKind::UnknownFlags);
+        let err = parse_err(&SMALL.replace("gnd s", "gnd slow=fast"));
+        assert_eq!(
+            err.to_string(),
+            "line 5: couldn't read slow coefficient from 'slow=fast'"
+        );
+    }
+    #[test]
+    fn test_start_properties() {
+        let text = SMALL.replace(
+            "player 0 1\n",
+            "player 0 1\nenemy 2 1 speed=40 range=3.5 angry=true look=left\n",
+        );
+        let level: Level = text.parse().unwrap();
+        let props = &level.starts()[1].2;
+        assert_eq!(props.int("speed"), Some(40));
+        assert_eq!(props.float("speed"), Some(40.0));
+        assert_eq!(props.float("range"), Some(3.5));
+        assert_eq!(props.int("range"), None);
+        assert_eq!(props.bool("angry"), Some(true));
+        assert_eq!(props.str("look"), Some("left"));
+        assert_eq!(props.get("missing"), None);
+        assert!(level.starts()[0].2.is_empty());
+        // whole-number floats stay floats when written back out
+        let mut floaty = level.starts()[1].2.clone();
+        floaty.insert("range", PropValue::Float(2.0));
+        assert_eq!(
+            floaty.iter().nth(2),
+            Some(("range", &PropValue::Float(2.0)))
+        );
+        assert_eq!("2.0".parse::<PropValue>(), Ok(PropValue::Float(2.0)));
+        // strings that would read back as something else are quoted
+        assert_eq!(PropValue::Str("left".into()).to_string(), "left");
+        assert_eq!(PropValue::Str("3".into()).to_string(), "\"3\"");
+        assert_eq!(
+            PropValue::Str("a \"b\"".into()).to_string(),
+            "\"a \\\"b\\\"\""
+        );
+        assert_eq!("\"3".parse::<PropValue>(), Ok(PropValue::Str("\"3".into())));
+        for (i, s) in [
+            "3", "true", "2.5", "", "far left", "a \"b\"", "\"", "\\", "c:\\x",
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let value = PropValue::Str(s.to_string());
+            assert_eq!(value.to_string().parse::<PropValue>(), Ok(value.clone()));
+            floaty.insert(&format!("s{i}"), value);
+        }
+        let mut quoted = level.clone();
+        let (etype, pos, _) = level.starts()[1].clone();
+        quoted.add_start(etype, pos, floaty);
+        assert_eq!(quoted.to_string().parse::<Level>().unwrap(), quoted);
+        let written = level.to_string();
+        assert!(written.contains("\nenemy 2 1 angry=true look=left range=3.5 speed=40\n"));
+        assert_eq!(written.parse::<Level>().unwrap(), level);
+
+        let err = parse_err(&SMALL.replace("player 0 1", "player 0 1 fast"));
+        assert_eq!(
+            err.to_string(),
+            "line 10: expected a key=value property, not 'fast'"
+        );
+        let err = parse_err(&SMALL.replace("player 0 1", "player 0 1 a=1 a=2"));
+        assert_eq!(err.kind, LevelParseErrorKind::DuplicateProperty);
+        assert_eq!((err.column, err.token.as_str()), (16, "a=2"));
+    }
+    fn parse_err(s: &str) -> LevelParseError {
+        match s.parse::<Level>() {
+            Ok(_) => panic!("level parsed but shouldn't have"),
+            Err(e) => e,
+        }
+    }
+    #[test]
+    fn test_parse_errors() {
+        let err = parse_err(&SMALL.replace("gnd gnd gnd", "gnd wsx gnd")).in_file("small.txt");
+        assert_eq!((err.section, err.line, err.column), (Section::Map, 8, 5));
+        assert_eq!(err.token, "wsx");
+        assert_eq!(err.to_string(), "small.txt:8: unknown legend symbol 'wsx'");
+
+        let err = parse_err(&SMALL.replace("0 0 16 16", "0 0 16"));
+        assert_eq!(err.kind, LevelParseErrorKind::Missing("sheet h"));
+        assert_eq!((err.line, err.column), (4, 13));
+
+        let err = parse_err(&SMALL.replace("player 0 1", "player 0 x"));
+        assert_eq!(err.to_string(), "line 10: couldn't read y from 'x'");
+
+        let err = parse_err(&SMALL.replace("air air air", "air air"));
+        assert_eq!(
+            err.kind,
+            LevelParseErrorKind::RowLength {
+                expected: 3,
+                found: 2
+            }
+        );
+        let err = parse_err(&SMALL.replace("gnd gnd gnd\n", ""));
+        assert_eq!(
+            err.to_string(),
+            "line 10: map has 1 rows but the level is 2 tall"
+        );
+    }
+}
@@ -0,0 +1,30 @@
+pub mod animation;
+pub mod campaign;
+pub mod collision;
+pub mod ecs;
+pub mod geom;
+pub mod grid;
+pub mod input;
+pub mod level;
+pub mod procgen;
+pub mod reach;
+pub mod runner;
+pub mod scene;
+pub mod systems;
+pub mod tiled;
+pub mod tilesheet;
+pub mod wfc;
+
+pub use animation::{Animation, AnimationState};
+pub use campaign::Campaign;
+pub use ecs::{Entity, Tags, World};
+pub use geom::{Rect, Vec2};
+pub use grid::Grid;
+pub use input::{HeldKeys, InputSource};
+pub use level::{Level, LevelParseError};
+pub use runner::{Game, Runner};
+pub use scene::{GameScenes, MessageScene, Scene, SceneStack, Transition};
+pub use tilesheet::Tilesheet;
+
+// Feel free to change this if you use a different tilesheet
+pub const TILE_SZ: usize = 16;
@@ -0,0 +1,1283 @@
+use crate::level::{start_to_world, world_to_start, EntityType, Level, Properties};
+use crate::TILE_SZ;
+use rand::distributions::WeightedIndex;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::fmt;
+
+// Where an obstacle can go up and down its columns: standing on the ground,
+// floating with some open rows between it and the ground, or hanging from
+// the ceiling (the first solid tile above, or the top of the level)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Height {
+    Ground,
+    MidAir(u16),
+    Ceiling,
+}
+
+// One kind of obstacle the generator can place: where touching it sends you
+// (like an `obstacle` start line), the block of cells it covers and the
+// legend symbols to paint over them, how often it comes up, how far it has
+// to be from the obstacle before it, and the heights it can go at.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObstacleKind {
+    pub to: String,
+    pub to_x: u16,
+    pub to_y: u16,
+    pub width: u16,
+    pub height: u16,
+    // a symbol per cell, a row at a time from the top; empty paints nothing
+    pub tiles: Vec<String>,
+    pub weight: u32,
+    pub min_distance: u16,
+    pub heights: Vec<Height>,
+}
+
+impl ObstacleKind {
+    // A single unpainted cell on the ground that sends you to `to` at
+    // (`to_x`, `to_y`)
+    pub fn new(to: &str, to_x: u16, to_y: u16) -> Self {
+        Self {
+            to: to.to_string(),
+            to_x,
+            to_y,
+            width: 1,
+            height: 1,
+            tiles: vec![],
+            weight: 1,
+            min_distance: 0,
+            heights: vec![Height::Ground],
+        }
+    }
+    // A single cell painted with `tile`
+    pub fn with_tile(self, tile: &str) -> Self {
+        self.with_footprint(1, 1, &[tile])
+    }
+    // A `width` by `height` block, painted with `tiles` a row at a time from
+    // the top, like the 2x2 sign `["wsl", "wsr", "wbl", "wbr"]`
+    pub fn with_footprint(self, width: u16, height: u16, tiles: &[&str]) -> Self {
+        assert!(width > 0 && height > 0, "An obstacle has to cover a cell");
+        assert!(
+            tiles.is_empty() || tiles.len() == width as usize * height as usize,
+            "A {width}x{height} obstacle needs {} tiles, not {}",
+            width * height,
+            tiles.len()
+        );
+        Self {
+            width,
+            height,
+            tiles: tiles.iter().map(|t| t.to_string()).collect(),
+            ..self
+        }
+    }
+    // How likely this kind is to be picked next, against the others' weights
+    pub fn with_weight(self, weight: u32) -> Self {
+        Self { weight, ..self }
+    }
+    // The fewest open columns between this kind and the obstacle before it,
+    // when that's more than the generator's spacing would leave
+    pub fn with_min_distance(self, min_distance: u16) -> Self {
+        Self {
+            min_distance,
+            ..self
+        }
+    }
+    // The heights it can go at; one of them is picked each time it's placed
+    pub fn with_heights(self, heights: &[Height]) -> Self {
+        assert!(!heights.is_empty(), "An obstacle needs somewhere to go");
+        Self {
+            heights: heights.to_vec(),
+            ..self
+        }
+    }
+}
+
+// An obstacle the generator put down: its kind (an index into the
+// catalogue), the height it went at, and the grid column and row of its top
+// left cell
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Placed {
+    pub kind: usize,
+    pub height: Height,
+    pub x: u16,
+    pub y: u16,
+}
+
+// A jump from flat ground at a steady forward speed, stepped a frame at a
+// time the way systems::controls, gravity and movement do it, so the
+// generator knows exactly which obstacles and gaps the player can manage.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct JumpArc {
+    pub jump_vel: f32,
+    pub gravity: f32,
+    pub speed: f32,
+    pub dt: f32,
+    // how wide and tall the jumping body's collider is
+    pub body_w: f32,
+    pub body_h: f32,
+}
+
+impl JumpArc {
+    pub fn new(jump_vel: f32, gravity: f32, speed: f32, body_w: f32, body_h: f32) -> Self {
+        assert!(gravity > 0.0, "A jump needs gravity to come back down");
+        Self {
+            jump_vel,
+            gravity,
+            speed,
+            dt: 1.0 / 60.0,
+            body_w,
+            body_h,
+        }
+    }
+    // The body's height after each frame in the air, ending with the frame
+    // it lands on
+    fn heights(&self) -> Vec<f32> {
+        // the fields are public, so check again that the jump ends
+        assert!(
+            self.gravity > 0.0 && self.dt > 0.0,
+            "A jump needs gravity and a frame time to come back down"
+        );
+        let (mut y, mut vel) = (0.0, self.jump_vel);
+        let mut heights = vec![];
+        while heights.is_empty() || y > 0.0 {
+            vel -= self.gravity * self.dt;
+            y += vel * self.dt;
+            heights.push(y);
+        }
+        heights
+    }
+    // How far the body goes from takeoff to landing
+    pub fn length(&self) -> f32 {
+        self.heights().len() as f32 * self.speed * self.dt
+    }
+    // How far past the takeoff point the body first and last is at least
+    // `height` up, if it gets that high at all
+    pub fn above(&self, height: f32) -> Option<(f32, f32)> {
+        let heights = self.heights();
+        let first = heights.iter().position(|y| *y >= height)?;
+        let last = heights.iter().rposition(|y| *y >= height)?;
+        let step = self.speed * self.dt;
+        Some(((first + 1) as f32 * step, (last + 1) as f32 * step))
+    }
+    // Whether an obstacle this wide and tall can be jumped over at all
+    pub fn clears(&self, width: f32, height: f32) -> bool {
+        self.above(height)
+            .is_some_and(|(up, down)| down - up >= width + self.body_w)
+    }
+    // The smallest gap between an obstacle and the next one (both `height`
+    // tall) that leaves room to land and take off again: jump the first as
+    // late as possible, then the second as early as possible.
+    pub fn min_gap(&self, height: f32) -> Option<f32> {
+        let (up, down) = self.above(height)?;
+        Some(self.body_w + up + self.length() - down)
+    }
+}
+
+// Scatters obstacles from a catalogue along a level's ground.  The same seed
+// always gives the same layout for the same level, so a race can be replayed
+// from its seed.
+pub struct ObstacleGenerator {
+    catalogue: Vec<ObstacleKind>,
+    min_spacing: u16,
+    max_spacing: u16,
+    start_x: u16,
+    end_x: Option<u16>,
+    jump: Option<JumpArc>,
+}
+
+impl ObstacleGenerator {
+    pub fn new(catalogue: Vec<ObstacleKind>) -> Self {
+        assert!(!catalogue.is_empty(), "The obstacle catalogue is empty");
+        Self {
+            catalogue,
+            min_spacing: 3,
+            max_spacing: 8,
+            start_x: 8,
+            end_x: None,
+            jump: None,
+        }
+    }
+    // How many empty columns go between one obstacle and the next
+    pub fn with_spacing(self, min_spacing: u16, max_spacing: u16) -> Self {
+        assert!(
+            min_spacing <= max_spacing,
+            "Minimum spacing {min_spacing} is more than the maximum {max_spacing}"
+        );
+        Self {
+            min_spacing,
+            max_spacing,
+            ..self
+        }
+    }
+    // The first column an obstacle can go in, to give the player a run-up
+    pub fn with_start(self, start_x: u16) -> Self {
+        Self { start_x, ..self }
+    }
+    // The column obstacles have to end before (the right edge if not set)
+    pub fn with_end(self, end_x: u16) -> Self {
+        Self {
+            end_x: Some(end_x),
+            ..self
+        }
+    }
+    // Only places obstacles the player can get past, with gaps they can land
+    // in and jump again from.  Each height a kind can go at has to leave
+    // room to run under it or be low enough to jump over; heights that
+    // don't are left out, and so are kinds with none left.  The spacing is
+    // widened to fit the tallest jump.  The ground is taken to be flat, so
+    // ceiling obstacles are checked as they're placed.
+    pub fn with_jump(self, jump: JumpArc) -> Self {
+        Self {
+            jump: Some(jump),
+            ..self
+        }
+    }
+    // The spacing generate actually uses: the minimum goes up to fit the
+    // jump, and the maximum goes up with it if it has to
+    pub fn spacing(&self) -> (u16, u16) {
+        // the top of every obstacle that has to be jumped, lifted ones included
+        let tallest = self
+            .usable_kinds()
+            .iter()
+            .flat_map(|kind| {
+                let kind = &self.catalogue[*kind];
+                self.usable_heights(kind)
+                    .into_iter()
+                    .map(|height| match height {
+                        Height::Ground => (0, kind.height),
+                        Height::MidAir(lift) => (lift, kind.height + lift),
+                        Height::Ceiling => (0, 0),
+                    })
+            })
+            .filter(|(lift, _)| !self.runs_under(*lift as usize))
+            .map(|(_, top)| top)
+            .max()
+            .unwrap_or(1);
+        let needed = self
+            .jump
+            .and_then(|jump| jump.min_gap((tallest as usize * TILE_SZ) as f32))
+            .map_or(0, |gap| (gap / TILE_SZ as f32).ceil() as u16);
+        let min = self.min_spacing.max(needed);
+        (min, self.max_spacing.max(min))
+    }
+    // The kinds generate can use, as indices into the catalogue
+    fn usable_kinds(&self) -> Vec<usize> {
+        (0..self.catalogue.len())
+            .filter(|kind| {
+                let kind = &self.catalogue[*kind];
+                kind.weight > 0 && !self.usable_heights(kind).is_empty()
+            })
+            .collect()
+    }
+    // The heights a kind can go at that the player can get past
+    fn usable_heights(&self, kind: &ObstacleKind) -> Vec<Height> {
+        let rows = kind.height as usize;
+        kind.heights
+            .iter()
+            .copied()
+            .filter(|height| match height {
+                Height::Ground => self.passable(kind.width, 0, rows),
+                Height::MidAir(lift) => {
+                    self.passable(kind.width, *lift as usize, rows + *lift as usize)
+                }
+                Height::Ceiling => true,
+            })
+            .collect()
+    }
+    // Whether the player fits under something `lift` rows off the ground
+    fn runs_under(&self, lift: usize) -> bool {
+        self.jump
+            .is_some_and(|jump| (lift * TILE_SZ) as f32 >= jump.body_h)
+    }
+    // Whether the player can run under, or jump over, an obstacle `width`
+    // columns wide with its bottom `lift` rows and its top `top` rows up
+    fn passable(&self, width: u16, lift: usize, top: usize) -> bool {
+        self.jump.is_none_or(|jump| {
+            self.runs_under(lift)
+                || jump.clears((width as usize * TILE_SZ) as f32, (top * TILE_SZ) as f32)
+        })
+    }
+    // The fewest empty columns between two of the obstacles it placed, to
+    // check a layout against the spacing it was meant to have
+    pub fn tightest_gap(&self, placed: &[Placed]) -> Option<u16> {
+        placed
+            .windows(2)
+            .map(|pair| pair[1].x - pair[0].x - self.catalogue[pair[0].kind].width)
+            .min()
+    }
+    // Adds obstacles to `level` from the start column to the end column,
+    // picking each kind by weight and then one of its heights.  Ground and
+    // ceiling are found on the first collision layer, and have to be level
+    // under (or over) the whole obstacle; columns where it doesn't fit, or
+    // overlaps something solid, are skipped.  Every cell an obstacle covers
+    // gets a start, and its tile if it has them.  Obstacles already in the
+    // level get the minimum spacing too.
+    pub fn generate(&self, level: &mut Level, seed: u64) -> Vec<Placed> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let (w, h) = (level.width(), level.height());
+        let (min_spacing, max_spacing) = self.spacing();
+        let kinds = self.usable_kinds();
+        if kinds.is_empty() {
+            return vec![];
+        }
+        let heights: Vec<Vec<Height>> = self
+            .catalogue
+            .iter()
+            .map(|kind| self.usable_heights(kind))
+            .collect();
+        let pick =
+            WeightedIndex::new(kinds.iter().map(|kind| self.catalogue[*kind].weight)).unwrap();
+        let Some(layer) = level.layers().iter().position(|l| l.collision) else {
+            return vec![];
+        };
+        let tiles: Vec<Vec<u8>> = self
+            .catalogue
+            .iter()
+            .map(|kind| {
+                kind.tiles
+                    .iter()
+                    .map(|sym| {
+                        level
+                            .symbol_index(sym)
+                            .unwrap_or_else(|| panic!("Obstacle tile {sym} isn't in the legend"))
+                    })
+                    .collect()
+            })
+            .collect();
+        let taken = taken_columns(level, min_spacing);
+        let solid = |level: &Level, x: usize, y: usize| {
+            let grid = &level.layers()[layer].grid;
+            level.tile_data(*grid.get(x, y).unwrap()).flags.solid
+        };
+        // the row just above the ground, if the column has any
+        let ground = |level: &Level, x: usize| {
+            (0..h)
+                .find(|y| solid(level, x, *y))
+                .filter(|y| *y > 0)
+                .map(|y| y - 1)
+        };
+        // the first open row under the ceiling
+        let ceiling = |level: &Level, x: usize| (0..h).find(|y| !solid(level, x, *y));
+        // the top row of an obstacle `rows` tall at `height` over these
+        // columns, if it fits there
+        let top_row = |level: &Level, cols: std::ops::Range<usize>, height: Height, rows: usize| {
+            let same = |mut tops: Vec<Option<usize>>| {
+                tops.dedup();
+                (tops.len() == 1).then_some(tops[0]).flatten()
+            };
+            let floor = same(cols.clone().map(|col| ground(level, col)).collect())?;
+            let top = match height {
+                Height::Ground => (floor + 1).checked_sub(rows)?,
+                Height::MidAir(lift) => (floor + 1).checked_sub(rows + lift as usize)?,
+                Height::Ceiling => {
+                    let top = same(cols.clone().map(|col| ceiling(level, col)).collect())?;
+                    // it can't reach all the way down to the ground, and
+                    // the player has to get past it
+                    let (lift, over) = ((floor + 1).checked_sub(top + rows)?, floor + 1 - top);
+                    (lift > 0 && self.passable(cols.len() as u16, lift, over)).then_some(top)?
+                }
+            };
+            cols.clone()
+                .all(|col| (top..top + rows).all(|row| !solid(level, col, row)))
+                .then_some(top)
+        };
+        let end = self.end_x.map_or(w, |end| w.min(end as usize));
+        let mut placed = vec![];
+        let mut x = self.start_x as usize;
+        let mut last_end = None;
+        loop {
+            let kind = kinds[rng.sample(&pick)];
+            let ObstacleKind {
+                to,
+                to_x,
+                to_y,
+                width,
+                height: rows,
+                min_distance,
+                ..
+            } = &self.catalogue[kind];
+            let (width, rows) = (*width as usize, *rows as usize);
+            if let Some(last_end) = last_end {
+                x = x.max(last_end + *min_distance as usize);
+            }
+            if x + width > end {
+                break;
+            }
+            let heights = &heights[kind];
+            let height = heights[rng.gen_range(0..heights.len())];
+            let top = if (x..x + width).any(|col| taken[col]) {
+                None
+            } else {
+                top_row(level, x..x + width, height, rows)
+            };
+            let Some(top) = top else {
+                x += 1;
+                continue;
+            };
+            for (i, (row, col)) in (top..top + rows)
+                .flat_map(|row| (x..x + width).map(move |col| (row, col)))
+                .enumerate()
+            {
+                level.add_start(
+                    EntityType::Obstacle(to.clone(), *to_x, *to_y),
+                    start_to_world(col as u16, row as u16, h as u16),
+                    Properties::default(),
+                );
+                if let Some(tile) = tiles[kind].get(i) {
+                    level.set_tile(layer, col, row, *tile);
+                }
+            }
+            placed.push(Placed {
+                kind,
+                height,
+                x: x as u16,
+                y: top as u16,
+            });
+            last_end = Some(x + width);
+            x += width + rng.gen_range(min_spacing..=max_spacing) as usize;
+        }
+        placed
+    }
+}
+
+// Builds flappy-bird style levels: pairs of columns, one hanging from the
+// top of the level and one standing on the ground (or the bottom), with a
+// gap between them to fly through.  Each gap is `gap` rows tall and starts
+// up to `variance` rows above or below the last one, so the path wanders
+// without jumping further than a flap can follow.
+pub struct GapGenerator {
+    kind: ObstacleKind,
+    gap: u16,
+    variance: u16,
+    min_spacing: u16,
+    max_spacing: u16,
+    start_x: u16,
+    end_x: Option<u16>,
+}
+
+// A pair of columns the generator put down: the grid column of its left
+// side, and the first open row of its gap and how many rows it has
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Gap {
+    pub x: u16,
+    pub top: u16,
+    pub size: u16,
+}
+
+impl GapGenerator {
+    // Every column pair is made of `kind`: it's `kind.width` columns wide,
+    // each cell of it sends you where `kind` says, and is painted with the
+    // first of `kind.tiles` if it has any
+    pub fn new(kind: ObstacleKind) -> Self {
+        Self {
+            kind,
+            gap: 4,
+            variance: 2,
+            min_spacing: 4,
+            max_spacing: 8,
+            start_x: 8,
+            end_x: None,
+        }
+    }
+    pub fn with_gap(self, gap: u16, variance: u16) -> Self {
+        assert!(gap > 0, "A gap has to have at least one row");
+        Self {
+            gap,
+            variance,
+            ..self
+        }
+    }
+    // The number of open columns between one pair and the next
+    pub fn with_spacing(self, min_spacing: u16, max_spacing: u16) -> Self {
+        assert!(
+            min_spacing <= max_spacing,
+            "Minimum spacing {min_spacing} is more than the maximum {max_spacing}"
+        );
+        Self {
+            min_spacing,
+            max_spacing,
+            ..self
+        }
+    }
+    pub fn with_start(self, start_x: u16) -> Self {
+        Self { start_x, ..self }
+    }
+    pub fn with_end(self, end_x: u16) -> Self {
+        Self {
+            end_x: Some(end_x),
+            ..self
+        }
+    }
+    // Adds column pairs to `level` from the start column to the end column.
+    // Every blocked cell gets a start, and the kind's tile on the first
+    // collision layer.  Columns near obstacles already in the level are
+    // skipped, as are columns too short to leave a row above and below the
+    // gap.
+    pub fn generate(&self, level: &mut Level, seed: u64) -> Vec<Gap> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let (w, h) = (level.width(), level.height());
+        let Some(layer) = level.layers().iter().position(|l| l.collision) else {
+            return vec![];
+        };
+        let tile = self.kind.tiles.first().map(|sym| {
+            level
+                .symbol_index(sym)
+                .unwrap_or_else(|| panic!("Obstacle tile {sym} isn't in the legend"))
+        });
+        let taken = taken_columns(level, self.min_spacing);
+        // columns run down to the ground, or the bottom of the level
+        let floor = |level: &Level, x: usize| {
+            let grid = &level.layers()[layer].grid;
+            (0..h)
+                .find(|y| level.tile_data(*grid.get(x, *y).unwrap()).flags.solid)
+                .unwrap_or(h)
+        };
+        let width = self.kind.width as usize;
+        let end = self.end_x.map_or(w, |end| w.min(end as usize));
+        let mut gaps: Vec<Gap> = vec![];
+        let mut x = self.start_x as usize;
+        while x + width <= end {
+            let bottom = (x..x + width).map(|col| floor(level, col)).min().unwrap();
+            if (x..x + width).any(|col| taken[col]) || bottom < self.gap as usize + 2 {
+                x += 1;
+                continue;
+            }
+            // keep a row of column above and below the gap
+            let (lo, hi) = (1, (bottom - self.gap as usize - 1) as u16);
+            let top = match gaps.last() {
+                Some(last) => {
+                    let (near_lo, near_hi) = (
+                        last.top.saturating_sub(self.variance).max(lo),
+                        (last.top + self.variance).min(hi),
+                    );
+                    if near_lo <= near_hi {
+                        rng.gen_range(near_lo..=near_hi)
+                    } else {
+                        last.top.clamp(lo, hi)
+                    }
+                }
+                None => rng.gen_range(lo..=hi),
+            };
+            let ObstacleKind { to, to_x, to_y, .. } = &self.kind;
+            let open = top as usize..(top + self.gap) as usize;
+            for col in x..x + width {
+                for row in (0..bottom).filter(|row| !open.contains(row)) {
+                    level.add_start(
+                        EntityType::Obstacle(to.clone(), *to_x, *to_y),
+                        start_to_world(col as u16, row as u16, h as u16),
+                        Properties::default(),
+                    );
+                    if let Some(tile) = tile {
+                        level.set_tile(layer, col, row, tile);
+                    }
+                }
+            }
+            gaps.push(Gap {
+                x: x as u16,
+                top,
+                size: self.gap,
+            });
+            x += width + rng.gen_range(self.min_spacing..=self.max_spacing) as usize;
+        }
+        gaps
+    }
+}
+
+// Rolls the ground up and down instead of leaving it flat.  The surface row
+// of each column comes from seeded value noise around a base row, and no
+// column is more than `max_step` rows above or below the one next to it, so
+// every step can be climbed (or jumped, see with_jump) and dropped back down.
+pub struct Terrain {
+    top: String,
+    fill: String,
+    sky: String,
+    edges: Option<(String, String)>,
+    base: Option<u16>,
+    amplitude: u16,
+    wavelength: u16,
+    max_step: u16,
+    start_x: u16,
+    end_x: Option<u16>,
+}
+
+impl Terrain {
+    // The surface is painted with `top` and everything under it with `fill`.
+    // Solid tiles left above the surface are replaced with `sky`.
+    pub fn new(top: &str, fill: &str, sky: &str) -> Self {
+        Self {
+            top: top.to_string(),
+            fill: fill.to_string(),
+            sky: sky.to_string(),
+            edges: None,
+            base: None,
+            amplitude: 3,
+            wavelength: 8,
+            max_step: 1,
+            start_x: 0,
+            end_x: None,
+        }
+    }
+    // Surface tiles for where the ground drops away to the left or right of
+    // a column (if it drops away on both sides, `top` is used)
+    pub fn with_edges(self, left: &str, right: &str) -> Self {
+        Self {
+            edges: Some((left.to_string(), right.to_string())),
+            ..self
+        }
+    }
+    // The row the surface rolls around; by default, wherever the ground is
+    // in the start column
+    pub fn with_base(self, base: u16) -> Self {
+        Self {
+            base: Some(base),
+            ..self
+        }
+    }
+    // How many rows above or below the base the surface can go
+    pub fn with_amplitude(self, amplitude: u16) -> Self {
+        Self { amplitude, ..self }
+    }
+    // Roughly how many columns from one hill to the next valley
+    pub fn with_wavelength(self, wavelength: u16) -> Self {
+        assert!(wavelength > 0, "The wavelength has to be at least a column");
+        Self { wavelength, ..self }
+    }
+    pub fn with_max_step(self, max_step: u16) -> Self {
+        Self { max_step, ..self }
+    }
+    // Only make steps this jump can get up: the body has to be above the
+    // step for at least its own width to land on it
+    pub fn with_jump(self, jump: JumpArc) -> Self {
+        let max_step = (1..)
+            .take_while(|rows: &u16| jump.clears(0.0, (*rows as usize * TILE_SZ) as f32))
+            .last()
+            .unwrap_or(0);
+        Self { max_step, ..self }
+    }
+    pub fn with_start(self, start_x: u16) -> Self {
+        Self { start_x, ..self }
+    }
+    pub fn with_end(self, end_x: u16) -> Self {
+        Self {
+            end_x: Some(end_x),
+            ..self
+        }
+    }
+    // Rewrites the first collision layer from the start column to the end
+    // column and returns the surface row it chose for each of them.  The
+    // new ground meets the columns on either side within `max_step` rows, so
+    // a run-up or the finish can be left flat.  Starts are left alone, so
+    // generate terrain before putting obstacles on it.  If one of the tiles
+    // isn't in the level's legend, the level isn't touched.
+    pub fn generate(&self, level: &mut Level, seed: u64) -> Result<Vec<u16>, MissingTile> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let (w, h) = (level.width(), level.height());
+        let symbol = |sym: &str| {
+            level
+                .symbol_index(sym)
+                .ok_or_else(|| MissingTile(sym.to_string()))
+        };
+        let (top, fill, sky) = (symbol(&self.top)?, symbol(&self.fill)?, symbol(&self.sky)?);
+        let edges = match &self.edges {
+            Some((left, right)) => Some((symbol(left)?, symbol(right)?)),
+            None => None,
+        };
+        let Some(layer) = level.layers().iter().position(|l| l.collision) else {
+            return Ok(vec![]);
+        };
+        // the first solid row of a column, or None for a pit
+        let surface = |level: &Level, x: usize| {
+            let grid = &level.layers()[layer].grid;
+            (0..h).find(|y| level.tile_data(*grid.get(x, *y).unwrap()).flags.solid)
+        };
+        let start = (self.start_x as usize).min(w);
+        let end = self.end_x.map_or(w, |end| w.min(end as usize)).max(start);
+        if start == end {
+            return Ok(vec![]);
+        }
+        let before = start.checked_sub(1).and_then(|x| surface(level, x));
+        let after = (end < w).then(|| surface(level, end)).flatten();
+        let base = self
+            .base
+            .map(|base| base as usize)
+            .or_else(|| surface(level, start))
+            .unwrap_or(h - 1);
+        // keep a row of sky above, and a row of fill under the lowest surface
+        let (lo, hi) = (1, h.saturating_sub(2).max(1));
+        let step = self.max_step as usize;
+        let noise = value_noise(&mut rng, end - start, self.wavelength as usize);
+        let mut rows: Vec<usize> = vec![];
+        for (i, n) in noise.iter().enumerate() {
+            let want = (base as f32 - n * self.amplitude as f32).round() as usize;
+            let mut range = (lo, hi);
+            if let Some(prev) = rows.last().copied().or(before) {
+                range = (prev.saturating_sub(step).max(lo), (prev + step).min(hi));
+            }
+            // leave enough columns to get to the ground after the end
+            if let Some(after) = after {
+                let reach = step * (end - start - i);
+                let (near_lo, near_hi) = (
+                    after.saturating_sub(reach).max(range.0),
+                    (after + reach).min(range.1),
+                );
+                if near_lo <= near_hi {
+                    range = (near_lo, near_hi);
+                }
+            }
+            rows.push(want.clamp(range.0, range.1.max(range.0)));
+        }
+        let row_at = |x: usize| match x {
+            x if (start..end).contains(&x) => Some(rows[x - start]),
+            x if x + 1 == start => before,
+            x if x == end => after,
+            _ => None,
+        };
+        for (i, row) in rows.iter().enumerate() {
+            let x = start + i;
+            // a pit counts as dropping away too
+            let drops = |side: Option<usize>| side.is_none_or(|r| r > *row);
+            let left = x > 0 && drops(row_at(x - 1));
+            let right = x + 1 < w && drops(row_at(x + 1));
+            let surface_tile = match edges {
+                Some((l, _)) if left && !right => l,
+                Some((_, r)) if right && !left => r,
+                _ => top,
+            };
+            for y in 0..h {
+                let tile = match y.cmp(row) {
+                    std::cmp::Ordering::Less => {
+                        let old = *level.layers()[layer].grid.get(x, y).unwrap();
+                        if !level.tile_data(old).flags.solid {
+                            continue;
+                        }
+                        sky
+                    }
+                    std::cmp::Ordering::Equal => surface_tile,
+                    std::cmp::Ordering::Greater => fill,
+                };
+                level.set_tile(layer, x, y, tile);
+            }
+        }
+        Ok(rows.into_iter().map(|row| row as u16).collect())
+    }
+}
+
+// A tile a generator was asked to paint that the level's legend doesn't have
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MissingTile(pub String);
+
+impl fmt::Display for MissingTile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "tile '{}' isn't in the level's legend", self.0)
+    }
+}
+
+impl std::error::Error for MissingTile {}
+
+// `n` values between -1 and 1 that wander smoothly: random heights every
+// `wavelength` steps eased into each other, plus half as much again of the
+// same at half the wavelength for some bumpiness
+fn value_noise(rng: &mut StdRng, n: usize, wavelength: usize) -> Vec<f32> {
+    let mut noise = vec![0.0; n];
+    for (wavelength, weight) in [
+        (wavelength, 2.0 / 3.0),
+        ((wavelength / 2).max(1), 1.0 / 3.0),
+    ] {
+        let points: Vec<f32> = (0..=n / wavelength + 1)
+            .map(|_| rng.gen_range(-1.0..=1.0))
+            .collect();
+        for (i, value) in noise.iter_mut().enumerate() {
+            let (cell, t) = (i / wavelength, (i % wavelength) as f32 / wavelength as f32);
+            let t = t * t * (3.0 - 2.0 * t);
+            *value += weight * (points[cell] + (points[cell + 1] - points[cell]) * t);
+        }
+    }
+    noise
+}
+
+// Which columns have an obstacle start already, or are within `spacing`
+// columns of one
+fn taken_columns(level: &Level, spacing: u16) -> Vec<bool> {
+    let (w, h) = (level.width(), level.height());
+    let mut taken = vec![false; w];
+    for (etype, pos, _) in level.starts() {
+        if let EntityType::Obstacle(..) = etype {
+            let x = world_to_start(*pos, h as u16).0 as usize;
+            let near = x.saturating_sub(spacing as usize)..x + spacing as usize + 1;
+            for t in taken.iter_mut().take(near.end).skip(near.start) {
+                *t = true;
+            }
+        }
+    }
+    taken
+}
+
+// Keeps a level going forever for endless runners.  The level is a window of
+// chunks, each `chunk` columns wide.  Once the player is past the second
+// chunk, the first is dropped and everything moves back a chunk, so positions
+// stay small enough for f32 to be precise, and a new chunk made of the
+// level's first column is added at the end and filled with obstacles.
+// `generator` says how to fill chunk n (counting from the start of the run),
+// so games can make later chunks harder.
+pub struct Endless {
+    chunk: usize,
+    seed: u64,
+    // chunks dropped off the start so far
+    dropped: u64,
+    // what new chunks are made of, from Level::column
+    column: Vec<Vec<u8>>,
+}
+
+impl Endless {
+    // Clears everything past the first chunk of `level` back to its first
+    // column and fills those chunks with obstacles.  The first chunk is left
+    // alone as a run-up.
+    pub fn new(
+        level: &mut Level,
+        chunk: usize,
+        seed: u64,
+        generator: impl Fn(u64) -> ObstacleGenerator,
+    ) -> Self {
+        assert!(
+            chunk > 0 && level.width() >= 2 * chunk && level.width().is_multiple_of(chunk),
+            "The level has to be a whole number of chunks, at least two"
+        );
+        let column = level.column(0);
+        let run_up = (chunk * TILE_SZ) as f32;
+        level.retain_starts(|(etype, pos, _)| *etype == EntityType::Player || pos.x < run_up);
+        for x in chunk..level.width() {
+            for (layer, tiles) in column.iter().enumerate() {
+                for (y, tile) in tiles.iter().enumerate() {
+                    level.set_tile(layer, x, y, *tile);
+                }
+            }
+        }
+        let endless = Self {
+            chunk,
+            seed,
+            dropped: 0,
+            column,
+        };
+        for n in 1..(level.width() / chunk) as u64 {
+            endless.fill(level, n, &generator);
+        }
+        endless
+    }
+    // Call after moving the player.  If they've gone past the second chunk,
+    // scrolls the level along and returns how far everything moved, which
+    // the game has to move its entities and camera by too.
+    pub fn update(
+        &mut self,
+        level: &mut Level,
+        player_x: f32,
+        generator: impl Fn(u64) -> ObstacleGenerator,
+    ) -> Option<f32> {
+        let chunk_px = (self.chunk * TILE_SZ) as f32;
+        if player_x < 2.0 * chunk_px {
+            return None;
+        }
+        level.scroll_left(self.chunk, &self.column);
+        self.dropped += 1;
+        let last = self.dropped + (level.width() / self.chunk) as u64 - 1;
+        self.fill(level, last, &generator);
+        Some(-chunk_px)
+    }
+    // How many chunks have been dropped off the start
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+    // Which chunk of the whole run `x` (in level coordinates) is in
+    pub fn chunk_at(&self, x: f32) -> u64 {
+        self.dropped + (x.max(0.0) / (self.chunk * TILE_SZ) as f32) as u64
+    }
+    // How far the level has moved back since the run started
+    pub fn offset(&self) -> f32 {
+        (self.dropped as usize * self.chunk * TILE_SZ) as f32
+    }
+    fn fill(&self, level: &mut Level, n: u64, generator: &impl Fn(u64) -> ObstacleGenerator) {
+        let start = ((n - self.dropped) as usize * self.chunk) as u16;
+        let seed = self.seed ^ n.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        generator(n)
+            .with_start(start)
+            .with_end(start + self.chunk as u16)
+            .generate(level, seed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geom::Vec2;
+
+    // 40 columns of ground with a pit at column 20 and a sign already at 30
+    fn level() -> Level {
+        let row = |tile: &str| {
+            (0..40)
+                .map(|x| if x == 20 { "air" } else { tile })
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let air = vec!["air"; 40].join(" ");
+        format!(
+            "flat 40 4\n====\nair o 0 0 16 16\ngnd s 16 0 16 16\nws o 16 192 16 16\n====\n{air}\n{air}\n{}\n{}\n====\nplayer 1 1\nobstacle flat 1 1 30 1\n",
+            row("gnd"),
+            row("gnd")
+        )
+        .parse()
+        .unwrap()
+    }
+
+    fn catalogue() -> Vec<ObstacleKind> {
+        vec![
+            ObstacleKind::new("flat", 1, 1).with_tile("ws"),
+            ObstacleKind::new("flat", 2, 1).with_footprint(2, 1, &[]),
+        ]
+    }
+
+    #[test]
+    fn test_generate_obstacles() {
+        let gen = ObstacleGenerator::new(catalogue())
+            .with_spacing(2, 4)
+            .with_start(4);
+        let mut level = level();
+        let placed = gen.generate(&mut level, 7);
+        assert!(!placed.is_empty());
+        for pair in placed.windows(2) {
+            let end = pair[0].x + catalogue()[pair[0].kind].width;
+            assert!(pair[1].x >= end + 2, "{pair:?}");
+        }
+        for p in placed.iter() {
+            let cols = p.x..p.x + catalogue()[p.kind].width;
+            assert!(p.x >= 4 && cols.end <= 40);
+            // on the ground, clear of the pit and the sign that was already there
+            assert_eq!(p.y, 1);
+            assert!(!cols.contains(&20) && !cols.contains(&30), "{p:?}");
+            if p.kind == 0 {
+                let tile = *level.layers()[0].grid.get(p.x as usize, 1).unwrap();
+                assert_eq!(Some(tile), level.symbol_index("ws"));
+            }
+        }
+        let columns: usize = placed
+            .iter()
+            .map(|p| catalogue()[p.kind].width as usize)
+            .sum();
+        assert_eq!(level.starts().len(), 2 + columns);
+
+        // the same seed gives the same level, and another seed doesn't
+        let mut again = self::level();
+        assert_eq!(gen.generate(&mut again, 7), placed);
+        assert_eq!(again, level);
+        let mut other = self::level();
+        assert_ne!(gen.generate(&mut other, 8), placed);
+    }
+
+    #[test]
+    fn test_jump_arc() {
+        // LocaThePug's jump at full speed: about 0.93s in the air and 84px
+        // long, clearing a tile's height for about 60px (a little less a frame at a time) of it
+        let arc = JumpArc::new(140.0, 300.0, 90.0, 16.0, 24.0);
+        assert!((arc.length() - 84.0).abs() <= 3.0, "{}", arc.length());
+        let (up, down) = arc.above(16.0).unwrap();
+        assert!((down - up - 60.0).abs() <= 6.0, "{up} {down}");
+        assert!(arc.clears(32.0, 16.0) && !arc.clears(64.0, 16.0));
+        assert_eq!(arc.above(40.0), None);
+        assert_eq!(arc.min_gap(40.0), None);
+        let gap = arc.min_gap(16.0).unwrap();
+        assert!((gap - 40.0).abs() <= 6.0, "{gap}");
+
+        // spacing is widened to fit the jump, and a kind too wide to clear
+        // is never placed
+        let mut kinds = catalogue();
+        kinds.push(ObstacleKind::new("flat", 2, 1).with_footprint(4, 1, &[]));
+        let gen = ObstacleGenerator::new(kinds)
+            .with_spacing(1, 2)
+            .with_start(2)
+            .with_jump(arc);
+        let (min, max) = gen.spacing();
+        assert_eq!((min, max), (3, 3));
+        for seed in 0..20 {
+            let mut level = level();
+            let placed = gen.generate(&mut level, seed);
+            assert!(placed.iter().all(|p| p.kind != 2));
+            assert!(gen.tightest_gap(&placed).unwrap() >= min);
+        }
+        // a jump that can't clear anything places nothing
+        let weak = ObstacleGenerator::new(catalogue())
+            .with_jump(JumpArc::new(60.0, 300.0, 90.0, 16.0, 24.0));
+        assert!(weak.generate(&mut level(), 1).is_empty());
+    }
+
+    #[test]
+    fn test_lifted_kinds() {
+        // a sign one row up leaves 16px under it for a 24px body, and its top
+        // is too high to jump; two rows up there's room to run under
+        let arc = JumpArc::new(140.0, 300.0, 90.0, 16.0, 24.0);
+        let lifted = |heights: &[Height]| {
+            ObstacleKind::new("sky", 1, 5)
+                .with_tile("ws")
+                .with_heights(heights)
+        };
+        let gen = ObstacleGenerator::new(vec![
+            lifted(&[Height::Ground]),
+            lifted(&[Height::MidAir(1), Height::MidAir(2)]),
+        ])
+        .with_spacing(1, 3)
+        .with_start(2)
+        .with_jump(arc);
+        let mut heights = vec![];
+        for seed in 0..10 {
+            heights.extend(gen.generate(&mut sky(), seed).iter().map(|p| p.height));
+        }
+        assert!(heights.contains(&Height::MidAir(2)) && heights.contains(&Height::Ground));
+        assert!(!heights.contains(&Height::MidAir(1)), "{heights:?}");
+        // and a kind with no height left isn't used at all
+        let stuck = ObstacleGenerator::new(vec![lifted(&[Height::MidAir(1)])]).with_jump(arc);
+        assert!(stuck.generate(&mut sky(), 1).is_empty());
+        // without a jump to check against, anything goes
+        let free = ObstacleGenerator::new(vec![lifted(&[Height::MidAir(1)])]).with_start(2);
+        assert!(!free.generate(&mut sky(), 1).is_empty());
+    }
+
+    // 40 columns, 10 rows of sky over a row of ground, with a sign at
+    // column 30 like the runner fixture
+    fn sky() -> Level {
+        let air = vec!["air"; 40].join(" ");
+        let gnd = vec!["gnd"; 40].join(" ");
+        let sky = vec![air; 10].join("\n");
+        let legend = "air o 0 0 16 16\ngnd s 16 0 16 16\nws o 16 192 16 16\nwsl o 0 16 16 16\nwsr o 16 16 16 16\nwbl o 0 32 16 16\nwbr o 16 32 16 16";
+        format!("sky 40 11\n====\n{legend}\n====\n{sky}\n{gnd}\n====\nplayer 1 5\nobstacle sky 1 5 30 9\n")
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_catalogue() {
+        let catalogue = vec![
+            ObstacleKind::new("sky", 1, 5)
+                .with_tile("ws")
+                .with_weight(3),
+            ObstacleKind::new("sky", 1, 5)
+                .with_footprint(2, 2, &["wsl", "wsr", "wbl", "wbr"])
+                .with_min_distance(5),
+            ObstacleKind::new("sky", 1, 5)
+                .with_tile("ws")
+                .with_heights(&[Height::MidAir(2), Height::Ceiling]),
+            ObstacleKind::new("sky", 1, 5).with_weight(0),
+        ];
+        let gen = ObstacleGenerator::new(catalogue.clone())
+            .with_spacing(1, 3)
+            .with_start(2);
+        let mut counts = [0; 4];
+        let mut heights = vec![];
+        for seed in 0..10 {
+            let mut level = sky();
+            let placed = gen.generate(&mut level, seed);
+            for (i, p) in placed.iter().enumerate() {
+                counts[p.kind] += 1;
+                heights.push(p.height);
+                let kind = &catalogue[p.kind];
+                // the block is painted and every cell of it is an obstacle
+                for (n, (row, col)) in (p.y..p.y + kind.height)
+                    .flat_map(|row| (p.x..p.x + kind.width).map(move |col| (row, col)))
+                    .enumerate()
+                {
+                    let cell = *level.layers()[0]
+                        .grid
+                        .get(col as usize, row as usize)
+                        .unwrap();
+                    if let Some(sym) = kind.tiles.get(n) {
+                        assert_eq!(Some(cell), level.symbol_index(sym));
+                    }
+                    let start = start_to_world(col, row, 11);
+                    assert!(level.starts().iter().any(|(_, pos, _)| *pos == start));
+                }
+                let bottom = p.y + kind.height - 1;
+                match p.height {
+                    Height::Ground => assert_eq!(bottom, 9, "{p:?}"),
+                    Height::MidAir(lift) => assert_eq!(bottom + lift, 9, "{p:?}"),
+                    Height::Ceiling => assert_eq!(p.y, 0, "{p:?}"),
+                }
+                if p.kind == 1 && i > 0 {
+                    let before = &placed[i - 1];
+                    assert!(p.x - before.x - catalogue[before.kind].width >= 5);
+                }
+            }
+        }
+        // weight 0 never comes up, and weight 3 comes up more than weight 1
+        assert_eq!(counts[3], 0);
+        assert!(counts[0] > counts[2] && counts[1] > 0, "{counts:?}");
+        assert!(heights.contains(&Height::MidAir(2)) && heights.contains(&Height::Ceiling));
+    }
+
+    #[test]
+    fn test_gaps() {
+        let gen = GapGenerator::new(catalogue().swap_remove(0))
+            .with_gap(3, 1)
+            .with_spacing(2, 4)
+            .with_start(4);
+        let mut level = sky();
+        let gaps = gen.generate(&mut level, 3);
+        assert!(gaps.len() >= 4, "{gaps:?}");
+        let ws = level.symbol_index("ws").unwrap();
+        for (i, gap) in gaps.iter().enumerate() {
+            assert!((28..33).all(|col| col != gap.x), "{gap:?}");
+            // a row of column above and below, and the gap open between them
+            assert!(gap.top >= 1 && gap.top + gap.size <= 9, "{gap:?}");
+            for row in 0..10 {
+                let open = (gap.top..gap.top + gap.size).contains(&row);
+                let cell = *level.layers()[0]
+                    .grid
+                    .get(gap.x as usize, row as usize)
+                    .unwrap();
+                assert_eq!(cell == ws, !open, "{gap:?} row {row}");
+                let start = start_to_world(gap.x, row, 11);
+                assert_eq!(
+                    level.starts().iter().any(|(_, pos, _)| *pos == start),
+                    !open
+                );
+            }
+            if i > 0 {
+                assert!(gap.top.abs_diff(gaps[i - 1].top) <= 1);
+                // at least two open columns between pairs
+                assert!(gap.x - gaps[i - 1].x >= 3);
+            }
+        }
+        // the ground is left alone
+        assert!((0..40).all(|x| level.layers()[0].grid.get(x, 10) == Some(&1)));
+
+        let mut again = sky();
+        assert_eq!(gen.generate(&mut again, 3), gaps);
+        assert_eq!(again, level);
+    }
+
+    // 40 columns, 8 rows of sky over 4 of ground with a top row
+    fn hills() -> Level {
+        let row = |tile: &str| vec![tile; 40].join(" ");
+        let sky = vec![row("air"); 8].join("\n");
+        let gnd = vec![row("gdc"); 3].join("\n");
+        let legend = "air o 0 0 16 16\ngdfc s 16 0 16 16\ngdc s 16 16 16 16\ngdl s 0 0 16 16\ngdr s 32 0 16 16";
+        format!(
+            "hills 40 12\n====\n{legend}\n====\n{sky}\n{}\n{gnd}\n====\nplayer 1 7\n",
+            row("gdfc")
+        )
+        .parse()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_terrain() {
+        let terrain = Terrain::new("gdfc", "gdc", "air")
+            .with_edges("gdl", "gdr")
+            .with_amplitude(4)
+            .with_wavelength(6)
+            .with_max_step(2)
+            .with_start(5)
+            .with_end(35);
+        let sym = |level: &Level, x: usize, y: usize| {
+            level
+                .symbol(*level.layers()[0].grid.get(x, y).unwrap())
+                .to_string()
+        };
+        let mut shapes = vec![];
+        for seed in 0..10 {
+            let mut level = hills();
+            let rows = terrain.generate(&mut level, seed).unwrap();
+            assert_eq!(rows.len(), 30);
+            // the run-up and finish stay flat, and the hills meet them
+            let all: Vec<u16> = [8; 5]
+                .into_iter()
+                .chain(rows.clone())
+                .chain([8; 5])
+                .collect();
+            for (x, pair) in all.windows(2).enumerate() {
+                assert!(pair[0].abs_diff(pair[1]) <= 2, "column {x}: {all:?}");
+            }
+            assert!(rows.iter().all(|row| (4..=11).contains(row)), "{rows:?}");
+            for (x, row) in all.iter().enumerate() {
+                let row = *row as usize;
+                assert!((0..row).all(|y| sym(&level, x, y) == "air"));
+                assert!((row + 1..12).all(|y| sym(&level, x, y) == "gdc"));
+                if (5..35).contains(&x) {
+                    let left = all[x - 1] as usize > row;
+                    let right = all[x + 1] as usize > row;
+                    let top = match (left, right) {
+                        (true, false) => "gdl",
+                        (false, true) => "gdr",
+                        _ => "gdfc",
+                    };
+                    assert_eq!(sym(&level, x, row), top, "column {x}: {all:?}");
+                }
+            }
+            let mut again = hills();
+            assert_eq!(terrain.generate(&mut again, seed), Ok(rows.clone()));
+            assert_eq!(again, level);
+            shapes.push(rows);
+        }
+        // it isn't flat, and it isn't the same every time
+        assert!(shapes.iter().all(|rows| rows.iter().any(|row| *row != 8)));
+        assert!(shapes.windows(2).any(|pair| pair[0] != pair[1]));
+
+        // LocaThePug's jump gets up one tile but not two, and a jump that
+        // can't get up anything leaves the ground flat
+        let loca = terrain.with_jump(JumpArc::new(140.0, 300.0, 90.0, 16.0, 24.0));
+        assert_eq!(loca.max_step, 1);
+        let rows = loca.generate(&mut hills(), 3).unwrap();
+        assert!(rows.windows(2).all(|pair| pair[0].abs_diff(pair[1]) <= 1));
+        let flat = loca.with_jump(JumpArc::new(60.0, 300.0, 90.0, 16.0, 24.0));
+        assert!(flat
+            .generate(&mut hills(), 3)
+            .unwrap()
+            .iter()
+            .all(|row| *row == 8));
+
+        // valleys stop a row above the bottom, which stays filled in
+        let deep = Terrain::new("gdfc", "gdc", "air").with_base(11);
+        for seed in 0..10 {
+            let mut level = hills();
+            let rows = deep.generate(&mut level, seed).unwrap();
+            assert!(rows.iter().all(|row| *row <= 10), "{rows:?}");
+            assert!((0..40).all(|x| sym(&level, x, 11) == "gdc"));
+        }
+
+        // a tile the legend doesn't have is an error, not a half-painted level
+        let mut level = hills();
+        let err = Terrain::new("gdfc", "dirt", "air").generate(&mut level, 3);
+        assert_eq!(err, Err(MissingTile("dirt".to_string())));
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "tile 'dirt' isn't in the level's legend"
+        );
+        assert_eq!(level, hills());
+    }
+
+    #[test]
+    fn test_endless() {
+        let sign = |_| ObstacleGenerator::new(catalogue()).with_spacing(2, 3);
+        let mut level = level();
+        // past the run-up the pit is filled in from the first column, and
+        // the sign that was there is replaced by generated ones
+        let mut stream = Endless::new(&mut level, 10, 5, sign);
+        assert_eq!(level.layers()[0].grid.get(20, 2), Some(&1));
+        assert!(level
+            .starts()
+            .iter()
+            .all(|(etype, pos, _)| *etype == EntityType::Player || pos.x >= 10.0 * 16.0));
+        assert_eq!(stream.update(&mut level, 100.0, sign), None);
+        let before: Vec<Vec2> = level.starts().iter().map(|(_, pos, _)| *pos).collect();
+        assert_eq!(stream.update(&mut level, 330.0, sign), Some(-160.0));
+        assert_eq!(
+            (level.width(), stream.dropped(), stream.offset()),
+            (40, 1, 160.0)
+        );
+        assert_eq!(stream.chunk_at(330.0 - 160.0), 2);
+        // the starts that were left moved back a chunk, and the new chunk has its own
+        let kept = before.iter().filter(|pos| pos.x >= 160.0).count();
+        assert!(level.starts()[..kept]
+            .iter()
+            .zip(before.iter().filter(|pos| pos.x >= 160.0))
+            .all(|((_, pos, _), old)| pos.x == old.x - 160.0));
+        assert!(level.starts()[kept..]
+            .iter()
+            .all(|(_, pos, _)| pos.x >= 30.0 * 16.0));
+        assert!(level.starts().len() > kept);
+    }
+}
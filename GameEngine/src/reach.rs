@@ -0,0 +1,440 @@
+use crate::ecs::{Body, Controller, Entity, World};
+use crate::geom::*;
+use crate::grid::Coord;
+use crate::input::HeldKeys;
+use crate::level::Level;
+use crate::{systems, Tags, TILE_SZ};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+// Works out whether a level can be finished without playing it.  The player
+// is stepped through the real controls, gravity, movement and tile collision
+// systems, trying every frame it could jump on, so it knows exactly what the
+// game would do.  Touching an obstacle costs a retry, so the search looks for
+// the route with the fewest: a level is beatable when that's none, and
+// otherwise the obstacles every such route touches are the ones nothing gets
+// past.
+pub struct Reach {
+    gravity: f32,
+    dt: f32,
+    bounds: Vec2,
+    goal_x: f32,
+    goal_tiles: Vec<Coord>,
+    max_states: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReachReport {
+    // whether the goal can be reached without touching an obstacle
+    pub reachable: bool,
+    // positions of the obstacles on the route to the goal that touches the
+    // fewest, in the order it touches them.  Other routes might touch
+    // different ones just as often; empty if the level is beatable, or if
+    // the goal can't be reached even by going through obstacles.
+    pub best_route: Vec<Vec2>,
+    // the obstacles from best_route that every route touching the fewest
+    // obstacles has to touch too, so getting rid of any of them makes the
+    // level easier
+    pub unavoidable: Vec<Vec2>,
+    // the rightmost point the player can get to without touching anything
+    pub furthest: Vec2,
+    // how many player states were tried, and whether the search stopped at
+    // its limit before it could say for sure
+    pub states: usize,
+    pub gave_up: bool,
+}
+
+// Everything about the player that changes from frame to frame
+#[derive(Clone, Copy)]
+struct PlayerState {
+    pos: Vec2,
+    vel: Vec2,
+    body: Body,
+    ctrl: Controller,
+}
+
+impl PlayerState {
+    // Only identical states are searched once, since the systems are
+    // deterministic and rounding would merge states a subpixel apart that
+    // can land differently.  Runs still meet up: x only depends on how long
+    // the player has been running, and landing snaps y to the ground.  The
+    // controller's jump timer only drives animations, so it's left out.
+    fn key(&self) -> (u32, u32, u32, u32, bool, Option<u32>) {
+        // adding 0.0 turns -0.0 into 0.0, which behaves the same
+        let bits = |x: f32| (x + 0.0).to_bits();
+        (
+            bits(self.pos.x),
+            bits(self.pos.y),
+            bits(self.vel.x),
+            bits(self.vel.y),
+            self.body.grounded,
+            self.body.touching.slow.map(bits),
+        )
+    }
+}
+
+struct Node {
+    player: PlayerState,
+    contacts: Vec<Entity>,
+    parent: Option<usize>,
+    // the obstacles first touched on the step into this node
+    hits: Vec<Entity>,
+    at_goal: bool,
+}
+
+// What one search found
+struct Search {
+    // the obstacles the cheapest route to the goal touches, in order, if
+    // there's a route at all
+    route: Option<Vec<Entity>>,
+    furthest: Vec2,
+    states: usize,
+    gave_up: bool,
+}
+
+impl Reach {
+    // `bounds` is the area the game keeps the player in with
+    // systems::keep_in_bounds
+    pub fn new(gravity: f32, dt: f32, bounds: Vec2) -> Self {
+        Self {
+            gravity,
+            dt,
+            bounds,
+            goal_x: f32::INFINITY,
+            goal_tiles: vec![],
+            max_states: 1_000_000,
+        }
+    }
+    // Getting this far right, or touching an obstacle at least this far
+    // right, reaches the goal.  Goal tiles always do.
+    pub fn with_goal_x(self, goal_x: f32) -> Self {
+        Self { goal_x, ..self }
+    }
+    // Touching this grid cell reaches the goal too
+    pub fn with_goal_tile(mut self, tile: Coord) -> Self {
+        self.goal_tiles.push(tile);
+        self
+    }
+    // The most states each search tries before giving up
+    pub fn with_max_states(self, max_states: usize) -> Self {
+        Self { max_states, ..self }
+    }
+    // Searches from where `player` is in `world`.  The world should hold the
+    // level's obstacles, tagged OBSTACLE; nothing but the player moves.
+    pub fn check(&self, mut world: World, player: Entity, level: &Level) -> ReachReport {
+        // only the player moves, and it's never what contacts looks up
+        world.update_broadphase();
+        let start = PlayerState {
+            pos: *world.positions.get(player).unwrap(),
+            vel: *world.velocities.get(player).unwrap(),
+            body: *world.bodies.get(player).unwrap(),
+            ctrl: *world.controllers.get(player).unwrap(),
+        };
+        let best = self.search(&mut world, player, start, level, None);
+        let found = best.route.is_some();
+        let route = best.route.unwrap_or_default();
+        let (mut states, mut gave_up) = (best.states, best.gave_up);
+        // an obstacle is unavoidable if keeping away from it makes every
+        // route touch more obstacles, or leaves no route at all
+        let mut unavoidable: Vec<Entity> = vec![];
+        for e in route.iter() {
+            if unavoidable.contains(e) {
+                continue;
+            }
+            let without = self.search(&mut world, player, start, level, Some(*e));
+            states += without.states;
+            gave_up |= without.gave_up;
+            if without.route.is_none_or(|r| r.len() > route.len()) {
+                unavoidable.push(*e);
+            }
+        }
+        let positions = |es: &[Entity]| -> Vec<Vec2> {
+            es.iter()
+                .map(|e| *world.positions.get(*e).unwrap())
+                .collect()
+        };
+        ReachReport {
+            reachable: found && route.is_empty(),
+            best_route: positions(&route),
+            unavoidable: positions(&unavoidable),
+            furthest: best.furthest,
+            states,
+            gave_up,
+        }
+    }
+    // A cheapest-first search from `start`, never touching `avoid`
+    fn search(
+        &self,
+        world: &mut World,
+        player: Entity,
+        start: PlayerState,
+        level: &Level,
+        avoid: Option<Entity>,
+    ) -> Search {
+        let jump_key = start.ctrl.jump_key;
+        let goal_rects: Vec<Rect> = self
+            .goal_tiles
+            .iter()
+            .map(|tile| {
+                let pos = level.grid_to_world(*tile);
+                Rect {
+                    x: pos.x,
+                    y: pos.y,
+                    w: TILE_SZ as u16,
+                    h: TILE_SZ as u16,
+                }
+            })
+            .collect();
+        place(world, player, &start);
+        let mut nodes = vec![Node {
+            player: start,
+            contacts: contacts(world),
+            parent: None,
+            hits: vec![],
+            at_goal: false,
+        }];
+        let mut best = HashMap::from([(start.key(), 0)]);
+        let mut queue = BinaryHeap::from([Reverse((0, 0))]);
+        let mut furthest = start.pos;
+        let mut goal = None;
+        let mut gave_up = false;
+        while let Some(Reverse((cost, index))) = queue.pop() {
+            if best.get(&nodes[index].player.key()) != Some(&cost) {
+                continue;
+            }
+            if nodes[index].at_goal {
+                goal = Some(index);
+                break;
+            }
+            if nodes.len() >= self.max_states {
+                gave_up = true;
+                break;
+            }
+            let here = &nodes[index];
+            let can_jump = here.player.body.grounded || here.player.ctrl.air_jumps;
+            let mut steps = vec![];
+            for jump in [false, true] {
+                if jump && !can_jump {
+                    continue;
+                }
+                let mut next = here.player;
+                self.step(world, player, &mut next, level, jump, jump_key);
+                if next.body.touching.hazard {
+                    continue;
+                }
+                let rect = world.rect(player).unwrap();
+                let now = contacts(world);
+                if avoid.is_some_and(|e| now.contains(&e)) {
+                    continue;
+                }
+                let at_goal = next.body.touching.goal
+                    || next.pos.x >= self.goal_x
+                    || goal_rects.iter().any(|r| rect.overlap(*r).is_some())
+                    || now
+                        .iter()
+                        .any(|e| world.positions.get(*e).unwrap().x >= self.goal_x);
+                let hits: Vec<Entity> = now
+                    .iter()
+                    .filter(|e| !here.contacts.contains(e))
+                    .filter(|e| world.positions.get(**e).unwrap().x < self.goal_x)
+                    .copied()
+                    .collect();
+                steps.push(Node {
+                    player: next,
+                    contacts: now,
+                    parent: Some(index),
+                    hits,
+                    at_goal,
+                });
+            }
+            for node in steps {
+                let next_cost = cost + node.hits.len();
+                let key = node.player.key();
+                if best.get(&key).is_some_and(|known| *known <= next_cost) {
+                    continue;
+                }
+                best.insert(key, next_cost);
+                if next_cost == 0 && node.player.pos.x > furthest.x {
+                    furthest = node.player.pos;
+                }
+                nodes.push(node);
+                queue.push(Reverse((next_cost, nodes.len() - 1)));
+            }
+        }
+        // walk back along the route to the goal for the obstacles it touched
+        let route = goal.map(|goal| {
+            let mut route = vec![];
+            let mut at = Some(goal);
+            while let Some(index) = at {
+                route.extend(nodes[index].hits.iter().rev());
+                at = nodes[index].parent;
+            }
+            route.reverse();
+            route
+        });
+        Search {
+            route,
+            furthest,
+            states: nodes.len(),
+            gave_up,
+        }
+    }
+    // One frame of the game's systems, in the order the games run them
+    fn step(
+        &self,
+        world: &mut World,
+        player: Entity,
+        state: &mut PlayerState,
+        level: &Level,
+        jump: bool,
+        jump_key: frenderer::input::Key,
+    ) {
+        place(world, player, state);
+        let input = if jump {
+            HeldKeys::new(&[jump_key])
+        } else {
+            HeldKeys::default()
+        };
+        systems::controls(world, &input, self.dt);
+        systems::gravity(world, self.gravity, self.dt);
+        systems::movement(world, level, self.dt);
+        systems::keep_in_bounds(world, self.bounds.x, self.bounds.y);
+        systems::tile_collision(world, level);
+        *state = PlayerState {
+            pos: *world.positions.get(player).unwrap(),
+            vel: *world.velocities.get(player).unwrap(),
+            body: *world.bodies.get(player).unwrap(),
+            ctrl: *world.controllers.get(player).unwrap(),
+        };
+    }
+}
+
+// Puts the player back in `state`
+fn place(world: &mut World, player: Entity, state: &PlayerState) {
+    *world.positions.get_mut(player).unwrap() = state.pos;
+    *world.velocities.get_mut(player).unwrap() = state.vel;
+    *world.bodies.get_mut(player).unwrap() = state.body;
+    *world.controllers.get_mut(player).unwrap() = state.ctrl;
+}
+
+// The obstacles the player is touching
+fn contacts(world: &World) -> Vec<Entity> {
+    world
+        .contacts(Tags::PLAYER, Tags::OBSTACLE)
+        .into_iter()
+        .map(|(_, obstacle)| obstacle)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::Collider;
+    use crate::level::{start_to_world, TileFlags};
+    use frenderer::input::Key;
+
+    // 30 columns of flat ground, with a runner standing on it at column 2
+    // and a sign at column 12 for each of `signs` rows up from the ground
+    fn setup(signs: u16) -> (World, Entity, Level) {
+        let air = vec!["air"; 30].join(" ");
+        let gnd = vec!["gnd"; 30].join(" ");
+        let level: Level = format!(
+            "flat 30 6\n====\nair o 0 0 16 16\ngnd s 16 0 16 16\n====\n{air}\n{air}\n{air}\n{air}\n{air}\n{gnd}\n====\nplayer 2 4\n"
+        )
+        .parse()
+        .unwrap();
+        let mut world = World::default();
+        let player = world.spawn(start_to_world(2, 4, 6));
+        world.velocities.insert(player, Vec2 { x: 0.0, y: 0.0 });
+        world.colliders.insert(
+            player,
+            Collider {
+                offset: Vec2 { x: -8.0, y: -8.0 },
+                w: 16,
+                h: 16,
+            },
+        );
+        world.bodies.insert(
+            player,
+            Body {
+                gravity: 1.0,
+                grounded: true,
+                touching: TileFlags::OPEN,
+            },
+        );
+        world.controllers.insert(
+            player,
+            Controller {
+                run_acc: 180.0,
+                max_speed: 90.0,
+                ..Controller::new(Key::ArrowUp, 140.0, 0.25)
+            },
+        );
+        world.tags.insert(player, Tags::PLAYER);
+        for row in 0..signs {
+            sign(&mut world, 12, 4 - row);
+        }
+        (world, player, level)
+    }
+
+    fn sign(world: &mut World, x: u16, y: u16) {
+        let sign = world.spawn(start_to_world(x, y, 6));
+        world.colliders.insert(
+            sign,
+            Collider {
+                offset: Vec2 { x: -8.0, y: -8.0 },
+                w: 16,
+                h: 16,
+            },
+        );
+        world.tags.insert(sign, Tags::OBSTACLE);
+    }
+
+    #[test]
+    fn test_reach() {
+        let reach =
+            Reach::new(300.0, 1.0 / 60.0, Vec2 { x: 480.0, y: 96.0 }).with_goal_tile((25, 4));
+        let (world, player, level) = setup(1);
+        let report = reach.check(world, player, &level);
+        assert!(report.reachable && !report.gave_up, "{report:?}");
+        assert!(report.furthest.x >= 24.0 * 16.0);
+
+        // a sign three high can't be jumped, so the best route goes through it
+        let (world, player, level) = setup(3);
+        let report = reach.check(world, player, &level);
+        assert!(!report.reachable, "{report:?}");
+        assert!(!report.unavoidable.is_empty());
+        assert!(report
+            .unavoidable
+            .iter()
+            .all(|pos| report.best_route.contains(pos)));
+        assert!(report
+            .unavoidable
+            .iter()
+            .all(|pos| pos.x == start_to_world(12, 4, 6).x));
+        assert!(report.furthest.x < 12.0 * 16.0);
+
+        // and with no goal, nothing is reachable
+        let (world, player, level) = setup(0);
+        let report =
+            Reach::new(300.0, 1.0 / 60.0, Vec2 { x: 480.0, y: 96.0 }).check(world, player, &level);
+        assert!(!report.reachable && !report.gave_up && report.unavoidable.is_empty());
+    }
+
+    #[test]
+    fn test_alternative_routes() {
+        let reach =
+            Reach::new(300.0, 1.0 / 60.0, Vec2 { x: 480.0, y: 96.0 }).with_goal_tile((25, 4));
+        // a sign on the ground and one two rows up, with a gap between them
+        // just as tall as the runner: it can go through either sign, but not
+        // between them without touching one
+        let (mut world, player, level) = setup(0);
+        sign(&mut world, 12, 4);
+        sign(&mut world, 12, 2);
+        let report = reach.check(world, player, &level);
+        assert!(!report.reachable && !report.gave_up, "{report:?}");
+        assert_eq!(report.best_route.len(), 1, "{report:?}");
+        // so neither one is to blame
+        assert!(report.unavoidable.is_empty(), "{report:?}");
+    }
+}
@@ -0,0 +1,162 @@
+use assets_manager::AssetCache;
+use frenderer::{input::Input, Renderer};
+use winit::event::{Event, WindowEvent};
+
+/// The parts of a game the runner can't do for you.  The runner owns the
+/// window, renderer, asset cache and clock and calls into these.
+pub trait Game: Sized + 'static {
+    fn init(renderer: &mut Renderer, cache: &AssetCache) -> Self;
+    fn simulate(&mut self, input: &Input, dt: f32);
+    fn render(&mut self, renderer: &mut Renderer);
+    // Window events the runner doesn't handle itself (keyboard input still
+    // goes to the Input passed to simulate).
+    fn handle_event(&mut self, _event: &WindowEvent) {}
+    // Called once a frame after the asset cache has picked up any changed
+    // files, so the game can rebuild whatever it made from them.
+    fn hot_reload(&mut self, _renderer: &mut Renderer, _cache: &AssetCache) {}
+}
+
+pub struct Runner {
+    title: String,
+    window_size: (f64, f64),
+    render_size: (u32, u32),
+    dt: f32,
+    max_steps: usize,
+    music_path: Option<String>,
+}
+
+impl Runner {
+    pub fn new(title: &str, render_w: usize, render_h: usize) -> Self {
+        Self {
+            title: title.to_string(),
+            window_size: (1024.0, 768.0),
+            render_size: (render_w as u32, render_h as u32),
+            dt: 1.0 / 60.0,
+            max_steps: 8,
+            music_path: None,
+        }
+    }
+    pub fn with_window_size(self, w: f64, h: f64) -> Self {
+        Self {
+            window_size: (w, h),
+            ..self
+        }
+    }
+    pub fn with_timestep(self, dt: f32) -> Self {
+        Self { dt, ..self }
+    }
+    // How many simulation steps we're willing to run for one rendered frame.
+    // If a frame took longer than this (window dragged, debugger paused) we
+    // drop the extra time instead of trying to catch up forever.
+    pub fn with_max_steps(self, max_steps: usize) -> Self {
+        Self {
+            max_steps: max_steps.max(1),
+            ..self
+        }
+    }
+    pub fn with_music(self, music_path: &str) -> Self {
+        Self {
+            music_path: Some(music_path.to_string()),
+            ..self
+        }
+    }
+    pub fn run<G: Game>(self, cache: AssetCache) {
+        // Keep the stream alive until the event loop exits or the music stops
+        let _stream = self.music_path.as_deref().and_then(play_music);
+
+        let drv = frenderer::Driver::new(
+            winit::window::WindowBuilder::new()
+                .with_title(&self.title)
+                .with_inner_size(winit::dpi::LogicalSize::new(
+                    self.window_size.0,
+                    self.window_size.1,
+                )),
+            Some(self.render_size),
+        );
+
+        let dt = self.dt;
+        let max_steps = self.max_steps;
+        let mut input = Input::default();
+
+        let mut now = frenderer::clock::Instant::now();
+        let mut acc = 0.0;
+        drv.run_event_loop::<(), _>(
+            move |window, mut frend| {
+                let game = G::init(&mut frend, &cache);
+                (window, game, frend, cache)
+            },
+            move |event, target, (window, ref mut game, ref mut frend, ref cache)| match event {
+                Event::WindowEvent {
+                    event: WindowEvent::CloseRequested,
+                    ..
+                } => {
+                    target.exit();
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::Resized(size),
+                    ..
+                } => {
+                    if !frend.gpu.is_web() {
+                        frend.resize_surface(size.width, size.height);
+                    }
+                    window.request_redraw();
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::RedrawRequested,
+                    ..
+                } => {
+                    cache.hot_reload();
+                    game.hot_reload(frend, cache);
+                    let elapsed = now.elapsed().as_secs_f32();
+                    now = frenderer::clock::Instant::now();
+                    // Snap the accumulator so a long stall can't put us in a death spiral
+                    acc = (acc + elapsed).min(dt * max_steps as f32);
+                    // While we have time to spend
+                    while acc >= dt {
+                        // simulate a frame
+                        acc -= dt;
+                        game.simulate(&input, dt);
+                        input.next_frame();
+                    }
+                    game.render(frend);
+                    frend.render();
+                    window.request_redraw();
+                }
+                event => {
+                    if let Event::WindowEvent {
+                        event: ref window_event,
+                        ..
+                    } = event
+                    {
+                        game.handle_event(window_event);
+                    }
+                    input.process_input_event(&event);
+                }
+            },
+        )
+        .expect("event loop error");
+    }
+}
+
+// Plays a music file on the default output device in the background.  The
+// returned stream has to be kept alive for as long as the music should play.
+fn play_music(path: &str) -> Option<rodio::OutputStream> {
+    use rodio::{source::Source, Decoder, OutputStream};
+    use std::fs::File;
+    use std::io::BufReader;
+
+    let (stream, stream_handle) = OutputStream::try_default()
+        .map_err(|e| eprintln!("Couldn't open audio device: {e}"))
+        .ok()?;
+    let file = File::open(path)
+        .map_err(|e| eprintln!("Couldn't open music file {path}: {e}"))
+        .ok()?;
+    let source = Decoder::new(BufReader::new(file))
+        .map_err(|e| eprintln!("Couldn't decode music file {path}: {e}"))
+        .ok()?;
+    stream_handle
+        .play_raw(source.convert_samples())
+        .map_err(|e| eprintln!("Couldn't play music file {path}: {e}"))
+        .ok()?;
+    Some(stream)
+}
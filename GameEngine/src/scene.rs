@@ -0,0 +1,371 @@
+use std::fmt::Display;
+
+use frenderer::{
+    bitfont::BitFont,
+    input::{Input, Key},
+    sprites::{Camera2D, SheetRegion},
+    Renderer,
+};
+
+/// What the scene stack should do after a scene's simulate step.
+pub enum Transition<S> {
+    None,
+    Push(Box<dyn Scene<S>>),
+    Pop,
+    Replace(Box<dyn Scene<S>>),
+}
+
+/// One state of the game (title screen, playing, paused, ...).  `S` is
+/// whatever the game wants every scene to share, usually its level and
+/// entity state.
+pub trait Scene<S> {
+    fn simulate(&mut self, state: &mut S, input: &Input, dt: f32) -> Transition<S>;
+    fn render(&mut self, state: &mut S, frend: &mut Renderer);
+    // Overlays (like the pause screen) are drawn on top of the scene below
+    // them instead of hiding it.
+    fn is_overlay(&self) -> bool {
+        false
+    }
+    // Called the next time we render after this scene was popped or
+    // replaced, so it can clear out whatever it drew.
+    fn exit(&mut self, _state: &mut S, _frend: &mut Renderer) {}
+}
+
+/// Only the top scene is simulated; it and any scenes it overlays are
+/// rendered, bottom first.
+pub struct SceneStack<S> {
+    scenes: Vec<Box<dyn Scene<S>>>,
+    exited: Vec<Box<dyn Scene<S>>>,
+}
+
+impl<S> SceneStack<S> {
+    pub fn new(first: impl Scene<S> + 'static) -> Self {
+        Self {
+            scenes: vec![Box::new(first)],
+            exited: vec![],
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.scenes.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.scenes.is_empty()
+    }
+    pub fn push(&mut self, scene: Box<dyn Scene<S>>) {
+        self.scenes.push(scene);
+    }
+    pub fn pop(&mut self) {
+        if let Some(scene) = self.scenes.pop() {
+            self.exited.push(scene);
+        }
+    }
+    pub fn replace(&mut self, scene: Box<dyn Scene<S>>) {
+        self.pop();
+        self.push(scene);
+    }
+    pub fn apply(&mut self, transition: Transition<S>) {
+        match transition {
+            Transition::None => {}
+            Transition::Push(scene) => self.push(scene),
+            Transition::Pop => self.pop(),
+            Transition::Replace(scene) => self.replace(scene),
+        }
+    }
+    pub fn simulate(&mut self, state: &mut S, input: &Input, dt: f32) {
+        if let Some(top) = self.scenes.last_mut() {
+            let transition = top.simulate(state, input, dt);
+            self.apply(transition);
+        }
+    }
+    pub fn render(&mut self, state: &mut S, frend: &mut Renderer) {
+        for mut scene in self.exited.drain(..) {
+            scene.exit(state, frend);
+        }
+        // start from the topmost scene that isn't drawn over something else
+        let bottom = self
+            .scenes
+            .iter()
+            .rposition(|s| !s.is_overlay())
+            .unwrap_or(0);
+        for scene in self.scenes[bottom..].iter_mut() {
+            scene.render(state, frend);
+        }
+    }
+}
+
+// All three tilesets have a white 8x8 font for ' ' through DEL at the top,
+// laid out 32 characters to a row with a pixel between them.
+pub fn default_font() -> BitFont {
+    BitFont::with_sheet_region(
+        ' '..='\u{7f}',
+        SheetRegion::new(0, 448, 0, 0, 32 * 9, 3 * 9),
+        8,
+        8,
+        1,
+        1,
+    )
+}
+
+// What a message scene does when its key is pressed
+type OnKey<S> = Box<dyn FnMut(&mut S) -> Transition<S>>;
+
+/// A screen of centred text that waits for a key.  The built-in title,
+/// pause, game-over and win scenes are all message scenes; games can change
+/// their text, key, font and what happens when the key is pressed.
+///
+/// Message scenes draw into their own sprite group (made by the game with the
+/// tileset the font is on) whose camera they set to `camera`.
+pub struct MessageScene<S> {
+    group: usize,
+    camera: Camera2D,
+    font: BitFont,
+    char_height: f32,
+    lines: Vec<String>,
+    key: Key,
+    overlay: bool,
+    on_key: OnKey<S>,
+}
+
+impl<S> MessageScene<S> {
+    pub fn new(
+        group: usize,
+        camera: Camera2D,
+        lines: &[&str],
+        key: Key,
+        on_key: impl FnMut(&mut S) -> Transition<S> + 'static,
+    ) -> Self {
+        Self {
+            group,
+            camera,
+            font: default_font(),
+            char_height: 8.0,
+            lines: lines.iter().map(|l| l.to_string()).collect(),
+            key,
+            overlay: false,
+            on_key: Box::new(on_key),
+        }
+    }
+    pub fn title(
+        group: usize,
+        camera: Camera2D,
+        title: &str,
+        mut start: impl FnMut(&mut S) -> Box<dyn Scene<S>> + 'static,
+    ) -> Self {
+        Self::new(
+            group,
+            camera,
+            &[title, "", "Press Enter to start"],
+            Key::Enter,
+            move |state| Transition::Replace(start(state)),
+        )
+    }
+    pub fn pause(group: usize, camera: Camera2D) -> Self {
+        Self::new(
+            group,
+            camera,
+            &["Paused", "", "Press Escape to resume"],
+            Key::Escape,
+            |_| Transition::Pop,
+        )
+        .overlay(true)
+    }
+    pub fn game_over(
+        group: usize,
+        camera: Camera2D,
+        mut retry: impl FnMut(&mut S) -> Box<dyn Scene<S>> + 'static,
+    ) -> Self {
+        Self::new(
+            group,
+            camera,
+            &["Game over", "", "Press Enter to try again"],
+            Key::Enter,
+            move |state| Transition::Replace(retry(state)),
+        )
+    }
+    pub fn win(
+        group: usize,
+        camera: Camera2D,
+        mut again: impl FnMut(&mut S) -> Box<dyn Scene<S>> + 'static,
+    ) -> Self {
+        Self::new(
+            group,
+            camera,
+            &["You win!", "", "Press Enter to play again"],
+            Key::Enter,
+            move |state| Transition::Replace(again(state)),
+        )
+    }
+    // Says why the game can't go on, wrapping the message to fit the camera.
+    // There's nothing to do from here, so the key does nothing.
+    pub fn error(group: usize, camera: Camera2D, error: &impl Display) -> Self {
+        let mut lines = vec!["Something went wrong".to_string(), String::new()];
+        // a minimized window can be narrower than the margin
+        let cols = ((camera.screen_size[0] / 8.0) as usize)
+            .saturating_sub(2)
+            .max(1);
+        lines.extend(wrap(&error.to_string(), cols));
+        Self {
+            lines,
+            ..Self::new(group, camera, &[], Key::Escape, |_| Transition::None)
+        }
+    }
+    pub fn with_lines(self, lines: &[&str]) -> Self {
+        Self {
+            lines: lines.iter().map(|l| l.to_string()).collect(),
+            ..self
+        }
+    }
+    pub fn with_key(self, key: Key) -> Self {
+        Self { key, ..self }
+    }
+    pub fn with_font(self, font: BitFont, char_height: f32) -> Self {
+        Self {
+            font,
+            char_height,
+            ..self
+        }
+    }
+    pub fn on_key(self, on_key: impl FnMut(&mut S) -> Transition<S> + 'static) -> Self {
+        Self {
+            on_key: Box::new(on_key),
+            ..self
+        }
+    }
+    pub fn overlay(self, overlay: bool) -> Self {
+        Self { overlay, ..self }
+    }
+}
+
+// Breaks text into lines of at most `cols` characters, at spaces if it can
+fn wrap(text: &str, cols: usize) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+        if !line.is_empty() && line.chars().count() + 1 + word.len() > cols {
+            lines.push(std::mem::take(&mut line));
+        }
+        while word.len() > cols {
+            let rest = word.split_off(cols);
+            lines.push(word.into_iter().collect());
+            word = rest;
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.extend(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+impl<S> Scene<S> for MessageScene<S> {
+    fn simulate(&mut self, state: &mut S, input: &Input, _dt: f32) -> Transition<S> {
+        if input.is_key_pressed(self.key) {
+            (self.on_key)(state)
+        } else {
+            Transition::None
+        }
+    }
+    fn render(&mut self, _state: &mut S, frend: &mut Renderer) {
+        let count = self.lines.iter().map(|l| l.chars().count()).sum();
+        frend.sprite_group_resize(self.group, count);
+        frend.sprite_group_set_camera(self.group, self.camera);
+        let (trfs, uvs) = frend.sprites_mut(self.group, ..);
+        let [cx, cy] = [
+            self.camera.screen_pos[0] + self.camera.screen_size[0] / 2.0,
+            self.camera.screen_pos[1] + self.camera.screen_size[1] / 2.0,
+        ];
+        let line_h = self.char_height * 1.5;
+        // y goes up, so the first line is the highest one
+        let mut y = cy + line_h * self.lines.len() as f32 / 2.0;
+        let mut used = 0;
+        for line in self.lines.iter() {
+            let w = line.chars().count() as f32 * self.char_height;
+            let (_, n) = self.font.draw_text(
+                &mut trfs[used..],
+                &mut uvs[used..],
+                line,
+                [cx - w / 2.0, y],
+                0,
+                self.char_height,
+            );
+            used += n;
+            y -= line_h;
+        }
+    }
+    fn is_overlay(&self) -> bool {
+        self.overlay
+    }
+    fn exit(&mut self, _state: &mut S, frend: &mut Renderer) {
+        frend.sprite_group_resize(self.group, 0);
+    }
+}
+
+/// A game's scenes and the state they share, or, if the game's content
+/// couldn't be loaded, a screen saying why instead of a crash.
+pub enum GameScenes<S> {
+    Loaded { scenes: SceneStack<S>, state: S },
+    Failed(MessageScene<()>),
+}
+
+impl<S> GameScenes<S> {
+    pub fn new(first: impl Scene<S> + 'static, state: S) -> Self {
+        Self::Loaded {
+            scenes: SceneStack::new(first),
+            state,
+        }
+    }
+    // Logs the error too, since it's easier to copy from the terminal
+    pub fn failed(group: usize, camera: Camera2D, error: &impl Display) -> Self {
+        eprintln!("{error}");
+        Self::Failed(MessageScene::error(group, camera, error))
+    }
+    pub fn simulate(&mut self, input: &Input, dt: f32) {
+        match self {
+            Self::Loaded { scenes, state } => scenes.simulate(state, input, dt),
+            Self::Failed(scene) => {
+                scene.simulate(&mut (), input, dt);
+            }
+        }
+    }
+    pub fn render(&mut self, frend: &mut Renderer) {
+        match self {
+            Self::Loaded { scenes, state } => scenes.render(state, frend),
+            Self::Failed(scene) => scene.render(&mut (), frend),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_wrap() {
+        assert_eq!(
+            wrap("the quick brown fox", 10),
+            vec!["the quick", "brown fox"]
+        );
+        // words longer than a line are split
+        assert_eq!(wrap("abcdefgh ij", 3), vec!["abc", "def", "gh", "ij"]);
+        assert_eq!(wrap("a b", 1), vec!["a", "b"]);
+        assert!(wrap("   ", 4).is_empty());
+    }
+    #[test]
+    fn test_error_fits_camera() {
+        let camera = |w: f32| Camera2D {
+            screen_pos: [0.0, 0.0],
+            screen_size: [w, 64.0],
+        };
+        let scene = MessageScene::<()>::error(0, camera(96.0), &"level1.txt:3: bad row");
+        assert_eq!(scene.lines[0], "Something went wrong");
+        assert!(scene.lines[2..].iter().all(|l| l.chars().count() <= 10));
+        // too narrow for any margin, like a minimized window
+        for w in [0.0, 8.0, 15.0] {
+            let scene = MessageScene::<()>::error(0, camera(w), &"oops");
+            assert_eq!(&scene.lines[2..], ["o", "o", "p", "s"]);
+        }
+    }
+}
@@ -0,0 +1,404 @@
+use crate::collision::compute_disp;
+use crate::ecs::{Entity, Tags, World};
+use crate::geom::*;
+use crate::input::InputSource;
+use crate::level::{Level, TileFlags};
+use rand::Rng;
+
+// The systems below each touch only the components they need, so a new kind
+// of entity just has to be spawned with the right components to get gravity,
+// movement, collision and animation.  Games call them once per frame in this
+// order: controls, patrol, gravity, movement, keep_in_bounds, tile_collision,
+// World::update_broadphase, animation, then handle their own triggers.
+
+// Jumping and running for everything with a controller
+pub fn controls(world: &mut World, input: &impl InputSource, dt: f32) {
+    for (e, ctrl) in world.controllers.iter_mut() {
+        let Some(vel) = world.velocities.get_mut(e) else {
+            continue;
+        };
+        let body = world.bodies.get(e);
+        let grounded = body.is_none_or(|b| b.grounded);
+        // slow tiles lower the top speed
+        let max_speed = ctrl.max_speed * body.and_then(|b| b.touching.slow).unwrap_or(1.0);
+        if input.is_key_down(ctrl.jump_key) && (grounded || ctrl.air_jumps) {
+            ctrl.jumping = true;
+            ctrl.jump_timer = ctrl.jump_time;
+            vel.y = ctrl.jump_vel;
+        }
+        if ctrl.jumping {
+            ctrl.jump_timer -= dt;
+            ctrl.jumping = ctrl.jump_timer > 0.0;
+        }
+        vel.x += ctrl.run_acc * dt;
+        vel.x = vel.x.clamp(-max_speed, max_speed);
+    }
+}
+
+// Walk in the current direction, turning around when the timer runs out or
+// at the edge of the patrol's bounds
+pub fn patrol(world: &mut World, dt: f32) {
+    let mut rng = rand::thread_rng();
+    for (e, patrol) in world.patrols.iter_mut() {
+        if let Some((min_x, max_x)) = patrol.bounds {
+            let x = world.positions.get(e).map_or(min_x, |pos| pos.x);
+            if (x <= min_x && patrol.dir < 0.0) || (x >= max_x && patrol.dir > 0.0) {
+                patrol.dir = -patrol.dir;
+            }
+        } else {
+            patrol.change_dir_timer -= dt;
+            if patrol.change_dir_timer <= 0.0 {
+                patrol.dir = -patrol.dir;
+                patrol.change_dir_timer = rng.gen_range(3.0..5.0);
+            }
+        }
+        if let Some(vel) = world.velocities.get_mut(e) {
+            vel.x = patrol.speed * patrol.dir;
+        }
+    }
+}
+
+pub fn gravity(world: &mut World, acc: f32, dt: f32) {
+    for (e, body) in world.bodies.iter() {
+        if let Some(vel) = world.velocities.get_mut(e) {
+            vel.y -= acc * body.gravity * dt;
+        }
+    }
+}
+
+// Moves everything by its velocity.  Bodies with a collider are swept
+// against the level's solid tiles, and the tops of one-way tiles, so however
+// fast they go they stop where they first touch one and slide along it for
+// the rest of the frame; tile_collision then lands them.  Obstacle entities
+// don't stop them, but the ones they pass right through are remembered for
+// World::contacts to report along with the ones they end up touching.
+pub fn movement(world: &mut World, level: &Level, dt: f32) {
+    let mut passed = vec![];
+    for (e, vel) in world.velocities.iter() {
+        let Some(mut pos) = world.positions.get(e).copied() else {
+            continue;
+        };
+        let mut delta = *vel * dt;
+        if let Some(col) = world.colliders.get(e).filter(|_| world.bodies.contains(e)) {
+            // one wall and one floor or ceiling, and a corner between them
+            for _ in 0..3 {
+                let rect = col.rect(pos);
+                let Some((hit, tile)) = first_hit(level, rect, delta) else {
+                    break;
+                };
+                passed.extend(swept_obstacles(world, e, rect, delta * hit.time).map(|o| (e, o)));
+                pos += delta * hit.time;
+                let rest = 1.0 - hit.time;
+                // line up exactly with the tile so nothing is left between
+                if hit.normal.x != 0.0 {
+                    pos.x = if hit.normal.x > 0.0 {
+                        tile.x + tile.w as f32
+                    } else {
+                        tile.x - col.w as f32
+                    } - col.offset.x;
+                    delta = Vec2 {
+                        x: 0.0,
+                        y: delta.y * rest,
+                    };
+                } else {
+                    pos.y = if hit.normal.y > 0.0 {
+                        tile.y + tile.h as f32
+                    } else {
+                        tile.y - col.h as f32
+                    } - col.offset.y;
+                    delta = Vec2 {
+                        x: delta.x * rest,
+                        y: 0.0,
+                    };
+                }
+            }
+            passed.extend(swept_obstacles(world, e, col.rect(pos), delta).map(|o| (e, o)));
+        }
+        if let Some(p) = world.positions.get_mut(e) {
+            *p = pos + delta;
+        }
+    }
+    passed.sort();
+    passed.dedup();
+    world.passed = passed;
+}
+
+// The area a rect covers moving by `delta`
+fn swept(rect: Rect, delta: Vec2) -> Rect {
+    Rect {
+        x: rect.x.min(rect.x + delta.x),
+        y: rect.y.min(rect.y + delta.y),
+        w: (rect.w as f32 + delta.x.abs()).ceil() as u16,
+        h: (rect.h as f32 + delta.y.abs()).ceil() as u16,
+    }
+}
+
+// The first tile a body moving by `delta` would run into, if any
+fn first_hit(level: &Level, rect: Rect, delta: Vec2) -> Option<(Hit, Rect)> {
+    level
+        .tiles_within(swept(rect, delta))
+        .filter(|(_, td)| td.flags.solid || td.flags.one_way)
+        .filter_map(|(tr, td)| {
+            let hit = rect.sweep(delta, tr)?;
+            // one-way tiles only stop things coming down onto them
+            let blocks =
+                td.flags.solid || (hit.normal.y > 0.0 && rect.y >= tr.y + tr.h as f32 - EPSILON);
+            blocks.then_some((hit, tr))
+        })
+        .min_by(|(a, _), (b, _)| a.time.total_cmp(&b.time))
+}
+
+// The obstacles other than `e` that a body moving by `delta` runs into.
+// Obstacles are looked up where they were at the last
+// World::update_broadphase.
+fn swept_obstacles<'a>(
+    world: &'a World,
+    e: Entity,
+    rect: Rect,
+    delta: Vec2,
+) -> impl Iterator<Item = Entity> + 'a {
+    world
+        .within(swept(rect, delta), Tags::OBSTACLE)
+        .into_iter()
+        .filter(move |o| *o != e)
+        .filter(move |o| {
+            world
+                .rect(*o)
+                .is_some_and(|r| rect.sweep(delta, r).is_some())
+        })
+}
+
+// How close a body's bottom has to be to a tile's top to be standing on it
+const EPSILON: f32 = 0.01;
+
+// Keeps colliders inside a `w` by `h` area with its corner at the origin;
+// anything resting on the bottom edge stops falling.
+pub fn keep_in_bounds(world: &mut World, w: f32, h: f32) {
+    for (e, col) in world.colliders.iter() {
+        let Some(pos) = world.positions.get_mut(e) else {
+            continue;
+        };
+        pos.x = pos.x.clamp(0.0, w - col.w as f32 / 2.0);
+        pos.y = pos.y.clamp(0.0, h - col.h as f32);
+        if pos.y <= 0.0 {
+            if let Some(vel) = world.velocities.get_mut(e) {
+                vel.y = 0.0;
+            }
+        }
+    }
+}
+
+// Pushes bodies out of solid tiles, and lands them if they were pushed up or
+// are resting on top of one.
+// One-way tiles only catch bodies falling onto their top half, bouncy tiles
+// throw them back up, and every body remembers the flags of the tiles it
+// ends up touching so games can react to hazards, goals and slow tiles.
+pub fn tile_collision(world: &mut World, level: &Level) {
+    let mut tiles = vec![];
+    for (e, body) in world.bodies.iter_mut() {
+        let (Some(col), Some(pos)) = (world.colliders.get(e), world.positions.get_mut(e)) else {
+            continue;
+        };
+        let start = col.rect(*pos);
+        tiles.clear();
+        tiles.extend(
+            level
+                .tiles_within(start)
+                .filter(|(tr, _)| start.overlap(*tr).is_some())
+                .map(|(tr, td)| (tr, td.flags)),
+        );
+        body.grounded = false;
+        let mut vel = world.velocities.get_mut(e);
+        let falling = vel.as_ref().is_none_or(|v| v.y <= 0.0);
+        for (tr, flags) in tiles.iter() {
+            let rect = col.rect(*pos);
+            let disp = if flags.solid {
+                compute_disp(rect, *tr)
+            } else if flags.one_way
+                && falling
+                && rect.y >= tr.y + tr.h as f32 / 2.0
+                && rect.overlap(*tr).is_some()
+            {
+                Vec2 {
+                    x: 0.0,
+                    y: tr.y + tr.h as f32 - rect.y,
+                }
+            } else {
+                continue;
+            };
+            *pos += disp;
+            // swept movement leaves landing bodies resting right on the tile
+            let resting = falling
+                && (rect.y - (tr.y + tr.h as f32)).abs() < EPSILON
+                && rect.overlap(*tr).is_some_and(|o| o.x > 0.0);
+            if disp.y > 0.0 || resting {
+                body.grounded = !flags.bouncy;
+                if let Some(vel) = vel.as_mut() {
+                    vel.y = if flags.bouncy { vel.y.abs() } else { 0.0 };
+                }
+            }
+        }
+        let end = col.rect(*pos);
+        body.touching = level
+            .tiles_within(end)
+            .filter(|(tr, _)| end.overlap(*tr).is_some())
+            .fold(TileFlags::OPEN, |flags, (_, td)| flags.union(td.flags));
+    }
+}
+
+// Picks idle/walk/jump animations from how each entity is moving, and
+// advances every sprite's animation clock
+pub fn animation(world: &mut World, dt: f32) {
+    for (e, sprite) in world.sprites.iter_mut() {
+        if let Some(animator) = world.animators.get(e) {
+            let vel = world
+                .velocities
+                .get(e)
+                .copied()
+                .unwrap_or(Vec2 { x: 0.0, y: 0.0 });
+            let jumping = world.controllers.get(e).is_some_and(|c| c.jumping);
+            let anim = if jumping && vel.y > 0.0 {
+                animator.jump_rise
+            } else if jumping {
+                animator.jump_fall
+            } else if vel.x.abs() > 0.1 {
+                animator.walk
+            } else {
+                animator.idle
+            };
+            sprite.anim.play(anim, false);
+        }
+        sprite.anim.tick(dt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::{Body, Collider};
+
+    // A thin floor with a one-way ledge above it, and a body high over both
+    fn setup(x: f32) -> (World, Entity, Level) {
+        let air = ["air"; 10].join(" ");
+        let ledge = ["ledge"; 10].join(" ");
+        let gnd = ["gnd"; 10].join(" ");
+        let level: Level = format!(
+            "thin 10 10\n====\nair o 0 0 16 16\ngnd s 16 0 16 16\nledge oneway 32 0 16 16\n====\n{air}\n{air}\n{air}\n{air}\n{air}\n{air}\n{ledge}\n{air}\n{gnd}\n{air}\n====\n"
+        )
+        .parse()
+        .unwrap();
+        let mut world = World::default();
+        let e = world.spawn(Vec2 { x, y: 150.0 });
+        world.colliders.insert(
+            e,
+            Collider {
+                offset: Vec2 { x: -8.0, y: 0.0 },
+                w: 16,
+                h: 16,
+            },
+        );
+        world.bodies.insert(
+            e,
+            Body {
+                gravity: 1.0,
+                grounded: false,
+                touching: TileFlags::OPEN,
+            },
+        );
+        (world, e, level)
+    }
+
+    #[test]
+    fn test_no_tunneling() {
+        // falling a whole ledge and floor in one frame still lands on the
+        // ledge, and a frame later is standing on it
+        let (mut world, e, level) = setup(40.0);
+        world.velocities.insert(e, Vec2 { x: 0.0, y: -9000.0 });
+        for _ in 0..2 {
+            gravity(&mut world, 300.0, 1.0 / 60.0);
+            movement(&mut world, &level, 1.0 / 60.0);
+            tile_collision(&mut world, &level);
+            assert_eq!(world.positions.get(e).unwrap().y, 64.0);
+            assert!(world.bodies.get(e).unwrap().grounded);
+            assert_eq!(world.velocities.get(e).unwrap().y, 0.0);
+        }
+
+        // jumping at the floor from underneath stops at its underside
+        // instead of coming out on top
+        let (mut world, e, level) = setup(40.0);
+        *world.positions.get_mut(e).unwrap() = Vec2 { x: 40.0, y: 0.0 };
+        world.velocities.insert(e, Vec2 { x: 0.0, y: 9000.0 });
+        movement(&mut world, &level, 1.0 / 60.0);
+        assert_eq!(world.positions.get(e).unwrap().y, 0.0);
+
+        // things without a body aren't stopped
+        let (mut world, e, level) = setup(40.0);
+        world.bodies.remove(e);
+        world.velocities.insert(
+            e,
+            Vec2 {
+                x: 60.0,
+                y: -9000.0,
+            },
+        );
+        movement(&mut world, &level, 1.0 / 60.0);
+        let pos = world.positions.get(e).unwrap();
+        assert!((pos.x - 41.0).abs() < 0.01 && pos.y.abs() < 0.01, "{pos:?}");
+    }
+
+    #[test]
+    fn test_no_tunneling_through_obstacles() {
+        // running past a sign in one frame still counts as touching it, and
+        // doesn't slow the runner down
+        let (mut world, e, level) = setup(40.0);
+        *world.positions.get_mut(e).unwrap() = Vec2 { x: 40.0, y: 100.0 };
+        world.velocities.insert(e, Vec2 { x: 9000.0, y: 0.0 });
+        world.tags.insert(e, Tags::PLAYER);
+        let sign = world.spawn(Vec2 { x: 128.0, y: 108.0 });
+        world.colliders.insert(
+            sign,
+            Collider {
+                offset: Vec2 { x: -8.0, y: -8.0 },
+                w: 16,
+                h: 16,
+            },
+        );
+        world.tags.insert(sign, Tags::OBSTACLE);
+        world.update_broadphase();
+        movement(&mut world, &level, 1.0 / 60.0);
+        let x = world.positions.get(e).unwrap().x;
+        assert!((x - 190.0).abs() < 0.01, "{x}");
+        world.update_broadphase();
+        assert_eq!(
+            world.contacts(Tags::PLAYER, Tags::OBSTACLE),
+            vec![(e, sign)]
+        );
+        // only for the frame it passed it in
+        movement(&mut world, &level, 1.0 / 60.0);
+        world.update_broadphase();
+        assert!(world.contacts(Tags::PLAYER, Tags::OBSTACLE).is_empty());
+
+        // falling past the corner of one keeps falling at full speed
+        *world.positions.get_mut(e).unwrap() = Vec2 { x: 116.0, y: 130.0 };
+        world.velocities.insert(e, Vec2 { x: 0.0, y: -3000.0 });
+        world.update_broadphase();
+        movement(&mut world, &level, 1.0 / 60.0);
+        let pos = *world.positions.get(e).unwrap();
+        assert!(pos.x == 116.0 && (pos.y - 80.0).abs() < 0.01, "{pos:?}");
+        assert_eq!(world.velocities.get(e).unwrap().y, -3000.0);
+        world.update_broadphase();
+        assert_eq!(
+            world.contacts(Tags::PLAYER, Tags::OBSTACLE),
+            vec![(e, sign)]
+        );
+
+        // and things that aren't obstacles aren't reported
+        *world.positions.get_mut(e).unwrap() = Vec2 { x: 40.0, y: 100.0 };
+        world.velocities.insert(e, Vec2 { x: 9000.0, y: 0.0 });
+        world.tags.insert(sign, Tags::NONE);
+        world.update_broadphase();
+        movement(&mut world, &level, 1.0 / 60.0);
+        world.update_broadphase();
+        assert!(world.contacts(Tags::PLAYER, Tags::OBSTACLE).is_empty());
+    }
+}
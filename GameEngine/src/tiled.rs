@@ -0,0 +1,863 @@
+use crate::grid::Grid;
+use crate::level::{start_to_world, EntityType, Layer, Level, TileData, TileFlags};
+use frenderer::sprites::SheetRegion;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::path::Path;
+
+// Imports maps made in Tiled (https://www.mapeditor.org), saved either as
+// XML (.tmx) or JSON (.tmj), into the same `Level` the text format gives.
+//
+// - Each tile layer becomes a level layer with the same name, drawn at its
+//   `depth` property (16 if it doesn't have one).  Layers with the boolean
+//   property `collision` set are the collision layers; if no layer has the
+//   property, they all are.
+// - Tiles come from the tileset's image position, so the tileset should be
+//   made from the game's tileset.png.  The boolean tile properties `solid`,
+//   `oneway` (or `one_way`), `hazard`, `bouncy` and `goal` and the float
+//   property `slow` set the tile's flags, and a string property `symbol`
+//   names it in the legend when the level is written back out (otherwise
+//   it's the tileset name and tile id, like `tiles.12`).  Empty cells become
+//   an open `empty` tile that draws nothing.  No two tiles can end up with
+//   the same symbol.
+// - Objects whose class (or type, or name) is player, enemy, mud or obstacle
+//   become starts in whichever tile they're in.  Obstacles need `level`,
+//   `to_x` and `to_y` properties for where they send you.  Any other object
+//   properties become the start's properties.
+// - The map's `name` property names the level, and its `bg` property (as
+//   "X Y W H") sets the background region.
+//
+// External tilesets (.tsx/.tsj) are read with the function passed in, or
+// from next to the map by `load`.  Infinite maps and base64 layer data
+// aren't supported; save with the CSV layer format instead.
+
+#[derive(Debug)]
+pub enum TiledError {
+    Io(String, std::io::Error),
+    Xml(roxmltree::Error),
+    Json(serde_json::Error),
+    // a required attribute or field
+    Missing(&'static str),
+    Unsupported(&'static str),
+    LayerSize { expected: usize, found: usize },
+    UnknownGid(u32),
+    TooManyTiles(usize),
+    UnknownObject(String),
+    BadProperty { name: String, value: String },
+    MissingProperty { object: String, name: &'static str },
+    DuplicateSymbol(String),
+}
+
+impl fmt::Display for TiledError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "couldn't read {path}: {e}"),
+            Self::Xml(e) => write!(f, "bad XML: {e}"),
+            Self::Json(e) => write!(f, "bad JSON: {e}"),
+            Self::Missing(what) => write!(f, "missing {what}"),
+            Self::Unsupported(what) => write!(f, "{what} aren't supported"),
+            Self::LayerSize { expected, found } => write!(
+                f,
+                "tile layer has {found} tiles but the map has {expected} cells"
+            ),
+            Self::UnknownGid(gid) => write!(f, "tile {gid} isn't in any tileset"),
+            Self::TooManyTiles(n) => {
+                write!(f, "map uses {n} different tiles but a level can have 256")
+            }
+            Self::UnknownObject(kind) => write!(f, "unknown object class '{kind}'"),
+            Self::BadProperty { name, value } => {
+                write!(f, "couldn't read property {name} from '{value}'")
+            }
+            Self::MissingProperty { object, name } => {
+                write!(f, "{object} object is missing property {name}")
+            }
+            Self::DuplicateSymbol(sym) => write!(f, "two tiles are both called '{sym}'"),
+        }
+    }
+}
+
+impl std::error::Error for TiledError {}
+
+// Property values, whatever their Tiled type, as the text Tiled shows
+type Properties = HashMap<String, String>;
+
+struct TilesetInfo {
+    first_gid: u32,
+    name: String,
+    tile_w: i16,
+    tile_h: i16,
+    columns: u32,
+    margin: u16,
+    spacing: u16,
+    tiles: HashMap<u32, Properties>,
+}
+
+impl TilesetInfo {
+    fn region(&self, id: u32) -> SheetRegion {
+        let col = (id % self.columns) as u16;
+        let row = (id / self.columns) as u16;
+        let x = self.margin + col * (self.tile_w as u16 + self.spacing);
+        let y = self.margin + row * (self.tile_h as u16 + self.spacing);
+        SheetRegion::new(0, x, y, 16, self.tile_w, self.tile_h)
+    }
+}
+
+struct TileLayer {
+    name: String,
+    props: Properties,
+    gids: Vec<u32>,
+}
+
+struct Object {
+    kind: String,
+    x: f32,
+    y: f32,
+    // tile objects hang up from their position instead of down
+    is_tile: bool,
+    props: Properties,
+}
+
+// What we need from either file format
+struct Map {
+    width: usize,
+    height: usize,
+    tile_w: f32,
+    tile_h: f32,
+    props: Properties,
+    tilesets: Vec<TilesetInfo>,
+    layers: Vec<TileLayer>,
+    objects: Vec<Object>,
+}
+
+// Tiled keeps flip and rotation flags in the top bits of each gid
+const GID_MASK: u32 = 0x0fff_ffff;
+
+pub fn from_tmx(
+    name: &str,
+    xml: &str,
+    read_external: impl FnMut(&str) -> std::io::Result<String>,
+) -> Result<Level, TiledError> {
+    build_level(name, tmx::map(xml, read_external)?)
+}
+
+pub fn from_tmj(
+    name: &str,
+    json: &str,
+    read_external: impl FnMut(&str) -> std::io::Result<String>,
+) -> Result<Level, TiledError> {
+    build_level(name, tmj::map(json, read_external)?)
+}
+
+// Loads a .tmx or .tmj file (by extension), and any external tilesets next
+// to it.  The level is named after the file unless the map says otherwise.
+pub fn load(path: impl AsRef<Path>) -> Result<Level, TiledError> {
+    let path = path.as_ref();
+    let read = |p: &Path| {
+        std::fs::read_to_string(p).map_err(|e| TiledError::Io(p.display().to_string(), e))
+    };
+    let text = read(path)?;
+    let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    let name = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let read_external = |src: &str| std::fs::read_to_string(dir.join(src));
+    if path
+        .extension()
+        .is_some_and(|ext| ext == "tmj" || ext == "json")
+    {
+        from_tmj(&name, &text, read_external)
+    } else {
+        from_tmx(&name, &text, read_external)
+    }
+}
+
+fn build_level(name: &str, map: Map) -> Result<Level, TiledError> {
+    let cells = map.width * map.height;
+    if map.layers.is_empty() {
+        return Err(TiledError::Missing("tile layer"));
+    }
+    for layer in map.layers.iter() {
+        if layer.gids.len() != cells {
+            return Err(TiledError::LayerSize {
+                expected: cells,
+                found: layer.gids.len(),
+            });
+        }
+    }
+    // Only the tiles the map uses go in the legend, in gid order
+    let used: BTreeSet<u32> = map
+        .layers
+        .iter()
+        .flat_map(|l| l.gids.iter().map(|gid| gid & GID_MASK))
+        .collect();
+    if used.len() > 256 {
+        return Err(TiledError::TooManyTiles(used.len()));
+    }
+    let mut legend = vec![];
+    let mut index = HashMap::new();
+    for gid in used {
+        index.insert(gid, legend.len() as u8);
+        if gid == 0 {
+            // the same as a legend line of `empty o 0 0 0 0`
+            let empty = TileData {
+                flags: TileFlags::OPEN,
+                sheet_region: SheetRegion::new(0, 0, 0, 16, 0, 0),
+            };
+            legend.push(("empty".to_string(), empty));
+            continue;
+        }
+        let tileset = map
+            .tilesets
+            .iter()
+            .filter(|ts| ts.first_gid <= gid)
+            .max_by_key(|ts| ts.first_gid)
+            .ok_or(TiledError::UnknownGid(gid))?;
+        let id = gid - tileset.first_gid;
+        let props = tileset.tiles.get(&id);
+        let prop = |name: &str| props.and_then(|p| p.get(name));
+        let symbol = match prop("symbol") {
+            Some(sym) => sym.clone(),
+            None => format!("{}.{id}", tileset.name.replace(char::is_whitespace, "_")),
+        };
+        // two tilesets with the same name, or a symbol property that names
+        // another tile, would make a legend that can't be read back
+        if legend.iter().any(|(other, _)| *other == symbol) {
+            return Err(TiledError::DuplicateSymbol(symbol));
+        }
+        let flag = |name: &str| prop(name).is_some_and(|v| v == "true");
+        let flags = TileFlags {
+            solid: flag("solid"),
+            one_way: flag("oneway") || flag("one_way"),
+            hazard: flag("hazard"),
+            slow: prop("slow").map(|v| number(v, "slow")).transpose()?,
+            bouncy: flag("bouncy"),
+            goal: flag("goal"),
+        };
+        let data = TileData {
+            flags,
+            sheet_region: tileset.region(id),
+        };
+        legend.push((symbol, data));
+    }
+    let any_collision = map.layers.iter().any(|l| l.props.contains_key("collision"));
+    let mut layers = vec![];
+    for layer in map.layers.iter() {
+        let depth = match layer.props.get("depth") {
+            Some(depth) => number(depth, "depth")?,
+            None => Layer::MAIN_DEPTH,
+        };
+        let collision = match layer.props.get("collision") {
+            Some(collision) => collision == "true",
+            None => !any_collision,
+        };
+        let gids = layer.gids.iter().map(|gid| index[&(gid & GID_MASK)]);
+        layers.push(Layer {
+            name: layer.name.replace(char::is_whitespace, "_"),
+            depth,
+            collision,
+            grid: Grid::new(map.width, map.height, gids),
+        });
+    }
+
+    let height = map.height as u16;
+    let mut starts = vec![];
+    for obj in map.objects.iter() {
+        let etype = match obj.kind.to_lowercase().as_str() {
+            "player" => EntityType::Player,
+            "enemy" => EntityType::Enemy,
+            "mud" => EntityType::Mud,
+            "obstacle" => {
+                let prop = |name: &'static str| {
+                    obj.props
+                        .get(name)
+                        .ok_or_else(|| TiledError::MissingProperty {
+                            object: obj.kind.clone(),
+                            name,
+                        })
+                };
+                EntityType::Obstacle(
+                    prop("level")?.clone(),
+                    number(prop("to_x")?, "to_x")?,
+                    number(prop("to_y")?, "to_y")?,
+                )
+            }
+            _ => return Err(TiledError::UnknownObject(obj.kind.clone())),
+        };
+        let gx = (obj.x / map.tile_w).floor().max(0.0) as u16;
+        let gy = if obj.is_tile {
+            (obj.y / map.tile_h).ceil() - 1.0
+        } else {
+            (obj.y / map.tile_h).floor()
+        };
+        let gy = (gy.max(0.0) as u16).min(height);
+        // the rest of the object's properties are the start's properties
+        let mut props = crate::level::Properties::default();
+        for (key, value) in obj.props.iter() {
+            let is_dest = matches!(etype, EntityType::Obstacle(..))
+                && ["level", "to_x", "to_y"].contains(&key.as_str());
+            if is_dest {
+                continue;
+            }
+            // a level file couldn't hold these
+            if key.is_empty() || value.contains(char::is_whitespace) || key.contains(['=', ' ']) {
+                return Err(TiledError::BadProperty {
+                    name: key.clone(),
+                    value: value.clone(),
+                });
+            }
+            let Ok(value) = value.parse();
+            props.insert(key, value);
+        }
+        starts.push((etype, start_to_world(gx, gy, height), props));
+    }
+
+    let bg = match map.props.get("bg") {
+        None => SheetRegion::ZERO,
+        Some(bg) => {
+            let bad = || TiledError::BadProperty {
+                name: "bg".to_string(),
+                value: bg.clone(),
+            };
+            let nums: Vec<&str> = bg.split_whitespace().collect();
+            let [x, y, w, h] = nums[..] else {
+                return Err(bad());
+            };
+            SheetRegion::rect(
+                x.parse().map_err(|_| bad())?,
+                y.parse().map_err(|_| bad())?,
+                w.parse().map_err(|_| bad())?,
+                h.parse().map_err(|_| bad())?,
+            )
+        }
+    };
+    let name = map.props.get("name").map(|s| s.as_str()).unwrap_or(name);
+    Ok(Level::with_layers(name, bg, layers, legend, starts))
+}
+
+fn number<T: std::str::FromStr>(value: &str, name: &str) -> Result<T, TiledError> {
+    value.trim().parse().map_err(|_| TiledError::BadProperty {
+        name: name.to_string(),
+        value: value.to_string(),
+    })
+}
+
+mod tmx {
+    use super::*;
+    use roxmltree::{Document, Node};
+
+    fn attr<T: std::str::FromStr>(node: Node, name: &'static str) -> Result<T, TiledError> {
+        number(node.attribute(name).ok_or(TiledError::Missing(name))?, name)
+    }
+    fn attr_or<T: std::str::FromStr>(
+        node: Node,
+        name: &'static str,
+        default: T,
+    ) -> Result<T, TiledError> {
+        match node.attribute(name) {
+            Some(_) => attr(node, name),
+            None => Ok(default),
+        }
+    }
+
+    fn properties(node: Node) -> Properties {
+        node.children()
+            .filter(|n| n.has_tag_name("properties"))
+            .flat_map(|n| n.children().filter(|n| n.has_tag_name("property")))
+            .filter_map(|p| {
+                let name = p.attribute("name")?.to_string();
+                // multi-line strings are kept in the element text
+                let value = p.attribute("value").or(p.text()).unwrap_or("");
+                Some((name, value.to_string()))
+            })
+            .collect()
+    }
+
+    fn tileset(node: Node, first_gid: u32) -> Result<TilesetInfo, TiledError> {
+        let tile_w: i16 = attr(node, "tilewidth")?;
+        let tile_h: i16 = attr(node, "tileheight")?;
+        let margin = attr_or(node, "margin", 0)?;
+        let spacing = attr_or(node, "spacing", 0)?;
+        let columns = match node.attribute("columns") {
+            Some(_) => attr(node, "columns")?,
+            // older files only give the image size
+            None => {
+                let image = node
+                    .children()
+                    .find(|n| n.has_tag_name("image"))
+                    .ok_or(TiledError::Missing("columns"))?;
+                let w: u32 = attr(image, "width")?;
+                (w + spacing as u32)
+                    .checked_sub(2 * margin as u32)
+                    .and_then(|w| w.checked_div(tile_w as u32 + spacing as u32))
+                    .ok_or(TiledError::Unsupported(
+                        "tilesets narrower than their margins",
+                    ))?
+            }
+        };
+        let mut tiles = HashMap::new();
+        for tile in node.children().filter(|n| n.has_tag_name("tile")) {
+            tiles.insert(attr(tile, "id")?, properties(tile));
+        }
+        Ok(TilesetInfo {
+            first_gid,
+            name: node.attribute("name").unwrap_or("tile").to_string(),
+            tile_w,
+            tile_h,
+            columns: columns.max(1),
+            margin,
+            spacing,
+            tiles,
+        })
+    }
+
+    fn layer(node: Node) -> Result<TileLayer, TiledError> {
+        let gids = gids(node)?;
+        Ok(TileLayer {
+            name: node.attribute("name").unwrap_or(Layer::MAIN).to_string(),
+            props: properties(node),
+            gids,
+        })
+    }
+
+    fn gids(node: Node) -> Result<Vec<u32>, TiledError> {
+        let data = node
+            .children()
+            .find(|n| n.has_tag_name("data"))
+            .ok_or(TiledError::Missing("layer data"))?;
+        match data.attribute("encoding") {
+            Some("csv") => data
+                .text()
+                .unwrap_or("")
+                .split(',')
+                .map(|gid| number(gid, "gid"))
+                .collect(),
+            Some(_) => Err(TiledError::Unsupported("base64 tile layers")),
+            None => data
+                .children()
+                .filter(|n| n.has_tag_name("tile"))
+                .map(|t| attr_or(t, "gid", 0))
+                .collect(),
+        }
+    }
+
+    // Tile and object layers, including ones inside groups
+    fn layers(node: Node, map: &mut Map) -> Result<(), TiledError> {
+        for child in node.children() {
+            match child.tag_name().name() {
+                "layer" => map.layers.push(layer(child)?),
+                "objectgroup" => {
+                    for obj in child.children().filter(|n| n.has_tag_name("object")) {
+                        let kind = obj
+                            .attribute("class")
+                            .or(obj.attribute("type"))
+                            .or(obj.attribute("name"))
+                            .unwrap_or("");
+                        map.objects.push(Object {
+                            kind: kind.to_string(),
+                            x: attr(obj, "x")?,
+                            y: attr(obj, "y")?,
+                            is_tile: obj.attribute("gid").is_some(),
+                            props: properties(obj),
+                        });
+                    }
+                }
+                "group" => layers(child, map)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn map(
+        xml: &str,
+        mut read_external: impl FnMut(&str) -> std::io::Result<String>,
+    ) -> Result<Map, TiledError> {
+        let doc = Document::parse(xml).map_err(TiledError::Xml)?;
+        let root = doc.root_element();
+        if root.attribute("infinite") == Some("1") {
+            return Err(TiledError::Unsupported("infinite maps"));
+        }
+        let mut map = Map {
+            width: attr(root, "width")?,
+            height: attr(root, "height")?,
+            tile_w: attr(root, "tilewidth")?,
+            tile_h: attr(root, "tileheight")?,
+            props: properties(root),
+            tilesets: vec![],
+            layers: vec![],
+            objects: vec![],
+        };
+        for ts in root.children().filter(|n| n.has_tag_name("tileset")) {
+            let first_gid = attr(ts, "firstgid")?;
+            let info = match ts.attribute("source") {
+                Some(src) => {
+                    let text =
+                        read_external(src).map_err(|e| TiledError::Io(src.to_string(), e))?;
+                    let doc = Document::parse(&text).map_err(TiledError::Xml)?;
+                    tileset(doc.root_element(), first_gid)?
+                }
+                None => tileset(ts, first_gid)?,
+            };
+            map.tilesets.push(info);
+        }
+        layers(root, &mut map)?;
+        Ok(map)
+    }
+}
+
+mod tmj {
+    use super::*;
+    use serde_json::Value;
+
+    fn field<'a>(v: &'a Value, name: &'static str) -> Result<&'a Value, TiledError> {
+        v.get(name).ok_or(TiledError::Missing(name))
+    }
+    fn num<T: TryFrom<u64>>(v: &Value, name: &'static str) -> Result<T, TiledError> {
+        let n = field(v, name)?;
+        n.as_u64()
+            .and_then(|n| T::try_from(n).ok())
+            .ok_or_else(|| TiledError::BadProperty {
+                name: name.to_string(),
+                value: n.to_string(),
+            })
+    }
+    fn num_or<T: TryFrom<u64>>(v: &Value, name: &'static str, default: T) -> Result<T, TiledError> {
+        match v.get(name) {
+            Some(_) => num(v, name),
+            None => Ok(default),
+        }
+    }
+    fn float(v: &Value, name: &'static str) -> Result<f32, TiledError> {
+        field(v, name)?
+            .as_f64()
+            .map(|f| f as f32)
+            .ok_or(TiledError::Missing(name))
+    }
+
+    fn properties(v: &Value) -> Properties {
+        let Some(props) = v.get("properties").and_then(|p| p.as_array()) else {
+            return Properties::new();
+        };
+        props
+            .iter()
+            .filter_map(|p| {
+                let name = p.get("name")?.as_str()?.to_string();
+                let value = match p.get("value")? {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                Some((name, value))
+            })
+            .collect()
+    }
+
+    fn tileset(v: &Value, first_gid: u32) -> Result<TilesetInfo, TiledError> {
+        let tile_w: i16 = num(v, "tilewidth")?;
+        let tile_h: i16 = num(v, "tileheight")?;
+        let margin = num_or(v, "margin", 0)?;
+        let spacing = num_or(v, "spacing", 0)?;
+        let columns: u32 = num(v, "columns")?;
+        let mut tiles = HashMap::new();
+        for tile in v
+            .get("tiles")
+            .and_then(|t| t.as_array())
+            .into_iter()
+            .flatten()
+        {
+            tiles.insert(num(tile, "id")?, properties(tile));
+        }
+        Ok(TilesetInfo {
+            first_gid,
+            name: v
+                .get("name")
+                .and_then(|n| n.as_str())
+                .unwrap_or("tile")
+                .to_string(),
+            tile_w,
+            tile_h,
+            columns: columns.max(1),
+            margin,
+            spacing,
+            tiles,
+        })
+    }
+
+    fn layers(v: &Value, map: &mut Map) -> Result<(), TiledError> {
+        for layer in v
+            .get("layers")
+            .and_then(|l| l.as_array())
+            .into_iter()
+            .flatten()
+        {
+            match layer.get("type").and_then(|t| t.as_str()) {
+                Some("tilelayer") => {
+                    if layer.get("encoding").and_then(|e| e.as_str()) == Some("base64") {
+                        return Err(TiledError::Unsupported("base64 tile layers"));
+                    }
+                    let data = field(layer, "data")?
+                        .as_array()
+                        .ok_or(TiledError::Missing("layer data"))?;
+                    let gids = data
+                        .iter()
+                        .map(|gid| gid.as_u64().map(|g| g as u32))
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or(TiledError::Missing("layer data"))?;
+                    let name = layer.get("name").and_then(|n| n.as_str());
+                    map.layers.push(TileLayer {
+                        name: name.unwrap_or(Layer::MAIN).to_string(),
+                        props: properties(layer),
+                        gids,
+                    });
+                }
+                Some("objectgroup") => {
+                    for obj in field(layer, "objects")?.as_array().into_iter().flatten() {
+                        let text = |name| {
+                            obj.get(name)
+                                .and_then(|t| t.as_str())
+                                .filter(|t| !t.is_empty())
+                        };
+                        let kind = text("class").or(text("type")).or(text("name"));
+                        map.objects.push(Object {
+                            kind: kind.unwrap_or("").to_string(),
+                            x: float(obj, "x")?,
+                            y: float(obj, "y")?,
+                            is_tile: obj.get("gid").is_some(),
+                            props: properties(obj),
+                        });
+                    }
+                }
+                Some("group") => layers(layer, map)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn map(
+        json: &str,
+        mut read_external: impl FnMut(&str) -> std::io::Result<String>,
+    ) -> Result<Map, TiledError> {
+        let root: Value = serde_json::from_str(json).map_err(TiledError::Json)?;
+        if root.get("infinite").and_then(|i| i.as_bool()) == Some(true) {
+            return Err(TiledError::Unsupported("infinite maps"));
+        }
+        let mut map = Map {
+            width: num(&root, "width")?,
+            height: num(&root, "height")?,
+            tile_w: float(&root, "tilewidth")?,
+            tile_h: float(&root, "tileheight")?,
+            props: properties(&root),
+            tilesets: vec![],
+            layers: vec![],
+            objects: vec![],
+        };
+        for ts in field(&root, "tilesets")?.as_array().into_iter().flatten() {
+            let first_gid = num(ts, "firstgid")?;
+            let info = match ts.get("source").and_then(|s| s.as_str()) {
+                Some(src) => {
+                    let text =
+                        read_external(src).map_err(|e| TiledError::Io(src.to_string(), e))?;
+                    let ext: Value = serde_json::from_str(&text).map_err(TiledError::Json)?;
+                    tileset(&ext, first_gid)?
+                }
+                None => tileset(ts, first_gid)?,
+            };
+            map.tilesets.push(info);
+        }
+        layers(&root, &mut map)?;
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The same little level as the text format tests
+    const SMALL: &str = "small 3 2
+bg 0 0 2 2
+====
+air o 0 0 16 16
+gnd s 16 0 16 16
+====
+air air air
+gnd gnd gnd
+====
+player 0 1
+obstacle small 1 1 2 1
+";
+    const SMALL_TMX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" width="3" height="2" tilewidth="16" tileheight="16" infinite="0">
+ <properties>
+  <property name="name" value="small"/>
+  <property name="bg" value="0 0 2 2"/>
+ </properties>
+ <tileset firstgid="1" source="tiles.tsx"/>
+ <layer id="1" name="main" width="3" height="2">
+  <data encoding="csv">
+1,1,1,
+2,2,2
+</data>
+ </layer>
+ <objectgroup id="2" name="starts">
+  <object id="1" type="player" x="4" y="20"/>
+  <object id="2" class="obstacle" x="32" y="32" gid="5">
+   <properties>
+    <property name="level" value="small"/>
+    <property name="to_x" type="int" value="1"/>
+    <property name="to_y" type="int" value="1"/>
+   </properties>
+  </object>
+ </objectgroup>
+</map>
+"#;
+    const TILES_TSX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.10" name="tiles" tilewidth="16" tileheight="16" tilecount="4" columns="2">
+ <image source="tileset.png" width="32" height="32"/>
+ <tile id="0"><properties><property name="symbol" value="air"/></properties></tile>
+ <tile id="1">
+  <properties>
+   <property name="symbol" value="gnd"/>
+   <property name="solid" type="bool" value="true"/>
+  </properties>
+ </tile>
+</tileset>
+"#;
+    const SMALL_TMJ: &str = r#"{
+ "width": 3, "height": 2, "tilewidth": 16, "tileheight": 16, "infinite": false,
+ "properties": [
+  {"name": "name", "type": "string", "value": "small"},
+  {"name": "bg", "type": "string", "value": "0 0 2 2"}
+ ],
+ "tilesets": [{
+  "firstgid": 1, "name": "tiles", "tilewidth": 16, "tileheight": 16, "columns": 2,
+  "tiles": [
+   {"id": 0, "properties": [{"name": "symbol", "type": "string", "value": "air"}]},
+   {"id": 1, "properties": [
+    {"name": "symbol", "type": "string", "value": "gnd"},
+    {"name": "solid", "type": "bool", "value": true}
+   ]}
+  ]
+ }],
+ "layers": [
+  {"type": "tilelayer", "name": "main", "width": 3, "height": 2, "data": [1, 1, 1, 2, 2, 2]},
+  {"type": "objectgroup", "name": "starts", "objects": [
+   {"id": 1, "type": "player", "x": 4, "y": 20},
+   {"id": 2, "type": "obstacle", "x": 32, "y": 32, "gid": 5, "properties": [
+    {"name": "level", "type": "string", "value": "small"},
+    {"name": "to_x", "type": "int", "value": 1},
+    {"name": "to_y", "type": "int", "value": 1}
+   ]}
+  ]}
+ ]
+}"#;
+
+    #[test]
+    fn test_import_matches_text_format() {
+        let expected: Level = SMALL.parse().unwrap();
+        let tmx = from_tmx("unnamed", SMALL_TMX, |src| {
+            assert_eq!(src, "tiles.tsx");
+            Ok(TILES_TSX.to_string())
+        })
+        .unwrap();
+        assert_eq!(tmx, expected);
+        let tmj = from_tmj("unnamed", SMALL_TMJ, |_| unreachable!()).unwrap();
+        assert_eq!(tmj, expected);
+        assert_eq!(tmj.to_string(), expected.to_string());
+
+        // the other tile flags come from properties too
+        let flagged = SMALL_TMJ.replace(
+            r#"{"name": "solid", "type": "bool", "value": true}"#,
+            r#"{"name": "solid", "type": "bool", "value": true},
+    {"name": "hazard", "type": "bool", "value": true},
+    {"name": "slow", "type": "float", "value": 0.5}"#,
+        );
+        let expected: Level = SMALL
+            .replace("gnd s", "gnd s,hazard,slow=0.5")
+            .parse()
+            .unwrap();
+        let tmj = from_tmj("unnamed", &flagged, |_| unreachable!()).unwrap();
+        assert_eq!(tmj, expected);
+
+        // and other object properties end up on the start
+        let tuned = SMALL_TMJ.replace(
+            r#""type": "player", "x": 4, "y": 20"#,
+            r#""type": "player", "x": 4, "y": 20, "properties": [
+    {"name": "speed", "type": "int", "value": 40},
+    {"name": "look", "type": "string", "value": "left"}
+   ]"#,
+        );
+        let expected: Level = SMALL
+            .replace("player 0 1", "player 0 1 look=left speed=40")
+            .parse()
+            .unwrap();
+        let tmj = from_tmj("unnamed", &tuned, |_| unreachable!()).unwrap();
+        assert_eq!(tmj, expected);
+    }
+    #[test]
+    fn test_import_layers() {
+        let layered = SMALL_TMJ.replace(
+            r#"  {"type": "tilelayer", "name": "main","#,
+            r#"  {"type": "tilelayer", "name": "decor", "data": [0, 1, 0, 0, 0, 0],
+   "properties": [{"name": "depth", "type": "int", "value": 15}]},
+  {"type": "tilelayer", "name": "main",
+   "properties": [{"name": "collision", "type": "bool", "value": true}],"#,
+        );
+        let level = from_tmj("small", &layered, |_| unreachable!()).unwrap();
+        let names: Vec<_> = level.layers().iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, ["decor", "main"]);
+        assert_eq!(level.layers()[0].depth, 15);
+        assert!(!level.layers()[0].collision && level.layers()[1].collision);
+        assert_eq!(level.to_string().parse::<Level>().unwrap(), level);
+    }
+    #[test]
+    fn test_import_errors() {
+        let unknown = SMALL_TMJ.replace(r#""type": "player""#, r#""type": "dragon""#);
+        let err = from_tmj("small", &unknown, |_| unreachable!()).unwrap_err();
+        assert_eq!(err.to_string(), "unknown object class 'dragon'");
+        let short = SMALL_TMX.replace("2,2,2", "2,2");
+        let err = from_tmx("small", &short, |_| Ok(TILES_TSX.to_string())).unwrap_err();
+        assert!(matches!(
+            err,
+            TiledError::LayerSize {
+                expected: 6,
+                found: 5
+            }
+        ));
+        // a tileset image too narrow for its margins
+        let narrow = TILES_TSX
+            .replace(r#" columns="2""#, r#" margin="8""#)
+            .replace(r#"width="32""#, r#"width="8""#);
+        let err = from_tmx("small", SMALL_TMX, |_| Ok(narrow.clone())).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "tilesets narrower than their margins aren't supported"
+        );
+        // two tiles with one symbol
+        let clash = SMALL_TMJ.replace(r#""value": "gnd""#, r#""value": "air""#);
+        let err = from_tmj("small", &clash, |_| unreachable!()).unwrap_err();
+        assert_eq!(err.to_string(), "two tiles are both called 'air'");
+    }
+    #[test]
+    fn test_import_symbols() {
+        // without symbol properties, tiles are named for their tileset and
+        // id, and can't run into each other or the empty tile
+        let unnamed = SMALL_TMJ
+            .replace(
+                r#"{"id": 0, "properties": [{"name": "symbol", "type": "string", "value": "air"}]},"#,
+                "",
+            )
+            .replace(
+                r#"{"name": "symbol", "type": "string", "value": "gnd"},"#,
+                r#"{"name": "oneway", "type": "bool", "value": true},"#,
+            )
+            .replace(r#""name": "tiles""#, r#""name": "empty""#)
+            .replace("[1, 1, 1, 2, 2, 2]", "[0, 1, 1, 2, 2, 2]");
+        let level = from_tmj("small", &unnamed, |_| unreachable!()).unwrap();
+        let symbols: Vec<&str> = (0..3).map(|i| level.symbol(i)).collect();
+        assert_eq!(symbols, ["empty", "empty.0", "empty.1"]);
+        assert!(level.tile_data(2).flags.one_way && level.tile_data(2).flags.solid);
+        assert_eq!(level.to_string().parse::<Level>().unwrap(), level);
+    }
+}
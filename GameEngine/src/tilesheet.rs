@@ -0,0 +1,81 @@
+use assets_manager::{asset::Png, AssetCache, ReloadId};
+use frenderer::{wgpu, Renderer};
+
+// A sprite sheet image loaded from the asset cache into a texture.  With
+// hot reloading on, `reload` copies a changed png into the same texture, so
+// sprite groups using it pick up the new pixels without being rebuilt.
+pub struct Tilesheet {
+    name: String,
+    texture: wgpu::Texture,
+    size: (u32, u32),
+    reload_id: ReloadId,
+}
+
+impl Tilesheet {
+    // Loads `content/{name}.png`.  The games can't run without their
+    // tileset, so a missing one is a panic.
+    pub fn load(renderer: &mut Renderer, cache: &AssetCache, name: &str) -> Self {
+        let handle = cache
+            .load::<Png>(name)
+            .unwrap_or_else(|e| panic!("Couldn't load tilesheet img {name}.png: {e}"));
+        let img = handle.read().0.to_rgba8();
+        let texture = renderer.create_array_texture(
+            &[&img],
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            img.dimensions(),
+            Some(name),
+        );
+        Self {
+            name: name.to_string(),
+            texture,
+            size: img.dimensions(),
+            reload_id: handle.last_reload_id(),
+        }
+    }
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+    // Re-uploads the image if the png changed since we last looked, returning
+    // whether it did.  The texture can't change size, so a resized image is
+    // reported and skipped until the game restarts.
+    pub fn reload(&mut self, renderer: &mut Renderer, cache: &AssetCache) -> bool {
+        let Ok(handle) = cache.load::<Png>(&self.name) else {
+            return false;
+        };
+        if handle.last_reload_id() == self.reload_id {
+            return false;
+        }
+        self.reload_id = handle.last_reload_id();
+        let img = handle.read().0.to_rgba8();
+        if img.dimensions() != self.size {
+            eprintln!(
+                "{}.png changed size from {:?} to {:?}; restart to see it",
+                self.name,
+                self.size,
+                img.dimensions()
+            );
+            return false;
+        }
+        let (w, h) = self.size;
+        renderer.gpu.queue().write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &img,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * w),
+                rows_per_image: Some(h),
+            },
+            wgpu::Extent3d {
+                width: w,
+                height: h,
+                depth_or_array_layers: 1,
+            },
+        );
+        true
+    }
+}
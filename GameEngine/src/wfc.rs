@@ -0,0 +1,436 @@
+use crate::grid::{Coord, Grid};
+use crate::level::Level;
+use rand::distributions::WeightedIndex;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::hash::Hash;
+use std::ops::Range;
+use std::str::FromStr;
+
+// Wave function collapse: fills part of a grid with tiles so that every pair
+// of neighbors is one the rules allow.  Every cell starts able to be any
+// tile; the cell with the fewest choices left is picked (by weight) one at a
+// time, and each pick narrows down its neighbors, and theirs, until nothing
+// is left to choose.  Rules can be learned from a hand-tiled example or
+// written out, e.g. for the door frame tiles:
+//
+//     dctl right dctr
+//     dctl down dccl
+//     dccl weight 3
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Side {
+    Left,
+    Up,
+    Right,
+    Down,
+}
+
+impl Side {
+    pub fn opposite(self) -> Self {
+        match self {
+            Side::Left => Side::Right,
+            Side::Up => Side::Down,
+            Side::Right => Side::Left,
+            Side::Down => Side::Up,
+        }
+    }
+    // Which side of `from` its neighbor `to` is on
+    fn between(from: Coord, to: Coord) -> Self {
+        match (
+            to.0 as isize - from.0 as isize,
+            to.1 as isize - from.1 as isize,
+        ) {
+            (-1, 0) => Side::Left,
+            (1, 0) => Side::Right,
+            (0, -1) => Side::Up,
+            (0, 1) => Side::Down,
+            _ => panic!("{to:?} isn't next to {from:?}"),
+        }
+    }
+}
+
+impl FromStr for Side {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "left" => Ok(Side::Left),
+            "up" => Ok(Side::Up),
+            "right" => Ok(Side::Right),
+            "down" => Ok(Side::Down),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let side = match self {
+            Side::Left => "left",
+            Side::Up => "up",
+            Side::Right => "right",
+            Side::Down => "down",
+        };
+        write!(f, "{side}")
+    }
+}
+
+// Which tiles can be next to which, and how often each should come up
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rules<T> {
+    tiles: Vec<T>,
+    weights: Vec<u32>,
+    // (a, side, b): b can be on that side of a
+    pairs: HashSet<(usize, Side, usize)>,
+}
+
+// Every attempt to fill the region ran into a cell no tile fits
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Contradiction;
+
+impl fmt::Display for Contradiction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no tiling fits the rules")
+    }
+}
+
+impl std::error::Error for Contradiction {}
+
+// A line of a rules file that isn't `A SIDE B` or `A weight N`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RulesParseError {
+    pub line: usize,
+    pub text: String,
+}
+
+impl fmt::Display for RulesParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: couldn't read rule '{}'", self.line, self.text)
+    }
+}
+
+impl std::error::Error for RulesParseError {}
+
+// How many times fill starts over after a contradiction before giving up
+const ATTEMPTS: usize = 20;
+
+impl<T: Clone + Eq + Hash> Default for Rules<T> {
+    fn default() -> Self {
+        Self {
+            tiles: vec![],
+            weights: vec![],
+            pairs: HashSet::new(),
+        }
+    }
+}
+
+impl<T: Clone + Eq + Hash> Rules<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    // Every pair of neighbors in `example` is allowed, and each tile is
+    // weighted by how often it appears there
+    pub fn learn(example: &Grid<T>) -> Self {
+        let mut rules = Self::new();
+        for y in 0..example.height() {
+            for x in 0..example.width() {
+                let tile = example[(x, y)].clone();
+                let index = rules.index(tile.clone());
+                rules.weights[index] += 1;
+                for nb in example.neighbors_4(x, y) {
+                    rules.allow(tile.clone(), Side::between((x, y), nb), example[nb].clone());
+                }
+            }
+        }
+        rules
+    }
+    fn index(&mut self, tile: T) -> usize {
+        match self.tiles.iter().position(|t| *t == tile) {
+            Some(index) => index,
+            None => {
+                self.tiles.push(tile);
+                self.weights.push(0);
+                self.tiles.len() - 1
+            }
+        }
+    }
+    // Lets `b` be on `side` of `a` (and so `a` on the other side of `b`).
+    // Tiles are added with no weight until they get one.
+    pub fn allow(&mut self, a: T, side: Side, b: T) {
+        let (a, b) = (self.index(a), self.index(b));
+        self.pairs.insert((a, side, b));
+        self.pairs.insert((b, side.opposite(), a));
+    }
+    pub fn set_weight(&mut self, tile: T, weight: u32) {
+        let index = self.index(tile);
+        self.weights[index] = weight;
+    }
+    pub fn tiles(&self) -> &[T] {
+        &self.tiles
+    }
+    pub fn weight(&self, tile: &T) -> Option<u32> {
+        let index = self.tiles.iter().position(|t| t == tile)?;
+        Some(self.weights[index])
+    }
+    pub fn allows(&self, a: &T, side: Side, b: &T) -> bool {
+        let find = |tile: &T| self.tiles.iter().position(|t| t == tile);
+        find(a)
+            .zip(find(b))
+            .is_some_and(|(a, b)| self.pairs.contains(&(a, side, b)))
+    }
+    // The same rules over other tiles, e.g. legend symbols to grid values.
+    // Tiles `f` has nothing for are dropped.
+    pub fn map<U: Clone + Eq + Hash>(&self, mut f: impl FnMut(&T) -> Option<U>) -> Rules<U> {
+        let mut rules = Rules::new();
+        let mapped: Vec<Option<U>> = self.tiles.iter().map(&mut f).collect();
+        for (tile, weight) in mapped.iter().zip(self.weights.iter()) {
+            if let Some(tile) = tile {
+                rules.set_weight(tile.clone(), *weight);
+            }
+        }
+        for (a, side, b) in self.pairs.iter() {
+            if let (Some(a), Some(b)) = (&mapped[*a], &mapped[*b]) {
+                rules.allow(a.clone(), *side, b.clone());
+            }
+        }
+        rules
+    }
+    // Fills the cells of `grid` in `xs` by `ys`.  Tiles around the region
+    // that the rules know about have to fit too.  The same seed always gives
+    // the same tiles; if every attempt runs into a contradiction the grid is
+    // left alone.
+    pub fn fill(
+        &self,
+        grid: &mut Grid<T>,
+        xs: Range<usize>,
+        ys: Range<usize>,
+        seed: u64,
+    ) -> Result<(), Contradiction> {
+        let n = self.tiles.len();
+        let (xs, ys) = (
+            xs.start..xs.end.min(grid.width()),
+            ys.start..ys.end.min(grid.height()),
+        );
+        if xs.is_empty() || ys.is_empty() {
+            return Ok(());
+        }
+        // fits[a][side][b]: b can be on that side of a
+        let mut fits: Vec<[Vec<bool>; 4]> = vec![std::array::from_fn(|_| vec![false; n]); n];
+        for (a, side, b) in self.pairs.iter() {
+            fits[*a][*side as usize][*b] = true;
+        }
+        let region = |(x, y): Coord| xs.contains(&x) && ys.contains(&y);
+        let cell = |(x, y): Coord| (y - ys.start) * xs.len() + (x - xs.start);
+        let coords: Vec<Coord> = ys
+            .clone()
+            .flat_map(|y| xs.clone().map(move |x| (x, y)))
+            .collect();
+        // what the tiles around the region allow
+        let mut start = vec![vec![true; n]; coords.len()];
+        for (i, here) in coords.iter().enumerate() {
+            for nb in grid.neighbors_4(here.0, here.1).filter(|nb| !region(*nb)) {
+                if let Some(tile) = self.tiles.iter().position(|t| *t == grid[nb]) {
+                    let side = Side::between(nb, *here) as usize;
+                    for (option, fit) in start[i].iter_mut().zip(fits[tile][side].iter()) {
+                        *option &= *fit;
+                    }
+                }
+            }
+        }
+        let mut rng = StdRng::seed_from_u64(seed);
+        for _ in 0..ATTEMPTS {
+            let mut options = start.clone();
+            let mut queue: VecDeque<usize> = (0..coords.len()).collect();
+            let mut ok = true;
+            loop {
+                // narrow down neighbors until nothing changes
+                while let Some(i) = queue.pop_front() {
+                    let here = coords[i];
+                    for nb in grid.neighbors_4(here.0, here.1).filter(|nb| region(*nb)) {
+                        let side = Side::between(here, nb) as usize;
+                        let j = cell(nb);
+                        let mut changed = false;
+                        for b in 0..n {
+                            if options[j][b] && !(0..n).any(|a| options[i][a] && fits[a][side][b]) {
+                                options[j][b] = false;
+                                changed = true;
+                            }
+                        }
+                        if changed {
+                            queue.push_back(j);
+                        }
+                    }
+                }
+                let counts: Vec<usize> = options
+                    .iter()
+                    .map(|o| o.iter().filter(|o| **o).count())
+                    .collect();
+                if counts.contains(&0) {
+                    ok = false;
+                    break;
+                }
+                // pick from the cells with the fewest choices left
+                let Some(fewest) = counts.iter().copied().filter(|c| *c > 1).min() else {
+                    break;
+                };
+                let undecided: Vec<usize> =
+                    (0..coords.len()).filter(|i| counts[*i] == fewest).collect();
+                let i = undecided[rng.gen_range(0..undecided.len())];
+                let choices: Vec<usize> = (0..n).filter(|t| options[i][*t]).collect();
+                let weights = choices.iter().map(|t| self.weights[*t].max(1));
+                let pick = choices[rng.sample(WeightedIndex::new(weights).unwrap())];
+                for (t, option) in options[i].iter_mut().enumerate() {
+                    *option = t == pick;
+                }
+                queue.push_back(i);
+            }
+            if ok {
+                for (i, here) in coords.iter().enumerate() {
+                    let tile = options[i].iter().position(|o| *o).unwrap();
+                    grid[*here] = self.tiles[tile].clone();
+                }
+                return Ok(());
+            }
+        }
+        Err(Contradiction)
+    }
+}
+
+// Rules from a hand-tiled layer of a level, by legend symbol so they can be
+// used on other levels with the same symbols
+pub fn learn_level(level: &Level, layer: usize) -> Rules<String> {
+    Rules::learn(&level.layers()[layer].grid).map(|tile| Some(level.symbol(*tile).to_string()))
+}
+
+// Fills the cells of one of `level`'s layers in `xs` by `ys` from rules over
+// legend symbols.  Symbols that aren't in the level's legend are left out.
+pub fn fill_level(
+    level: &mut Level,
+    layer: usize,
+    xs: Range<usize>,
+    ys: Range<usize>,
+    rules: &Rules<String>,
+    seed: u64,
+) -> Result<(), Contradiction> {
+    let rules = rules.map(|sym| level.symbol_index(sym));
+    let mut grid = level.layers()[layer].grid.clone();
+    rules.fill(&mut grid, xs.clone(), ys.clone(), seed)?;
+    for y in ys.start..ys.end.min(level.height()) {
+        for x in xs.start..xs.end.min(level.width()) {
+            level.set_tile(layer, x, y, grid[(x, y)]);
+        }
+    }
+    Ok(())
+}
+
+// One rule a line: `A SIDE B` lets B be on that side of A, and `A weight N`
+// weights A.  Blank lines and lines starting with # are skipped.
+impl FromStr for Rules<String> {
+    type Err = RulesParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rules = Rules::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err = || RulesParseError {
+                line: i + 1,
+                text: line.to_string(),
+            };
+            let [a, how, b] = line.split_whitespace().collect::<Vec<_>>()[..] else {
+                return Err(err());
+            };
+            if how == "weight" {
+                rules.set_weight(a.to_string(), b.parse().map_err(|_| err())?);
+            } else {
+                let side = how.parse().map_err(|_| err())?;
+                rules.allow(a.to_string(), side, b.to_string());
+            }
+        }
+        Ok(rules)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(rows: &[&str]) -> Grid<char> {
+        Grid::new(
+            rows[0].len(),
+            rows.len(),
+            rows.iter().flat_map(|row| row.chars()),
+        )
+    }
+
+    // every pair of neighbors in the grid is one the rules allow
+    fn consistent(rules: &Rules<char>, grid: &Grid<char>) -> bool {
+        (0..grid.height()).all(|y| {
+            (0..grid.width()).all(|x| {
+                grid.neighbors_4(x, y)
+                    .all(|nb| rules.allows(&grid[(x, y)], Side::between((x, y), nb), &grid[nb]))
+            })
+        })
+    }
+
+    #[test]
+    fn test_learned_rules() {
+        // a door frame in a wall, like the dc* tiles
+        let example = grid(&["......", ".ab...", ".cd...", ".ef...", "......"]);
+        let rules = Rules::learn(&example);
+        assert!(rules.allows(&'a', Side::Right, &'b') && rules.allows(&'b', Side::Left, &'a'));
+        assert!(!rules.allows(&'a', Side::Left, &'b') && !rules.allows(&'e', Side::Down, &'a'));
+        assert_eq!(
+            (rules.weight(&'.'), rules.weight(&'c')),
+            (Some(24), Some(1))
+        );
+
+        let mut filled = grid(&["..........", "..........", "..........", ".........."]);
+        rules.fill(&mut filled, 1..9, 0..4, 11).unwrap();
+        assert!(consistent(&rules, &filled));
+        let mut again = grid(&["..........", "..........", "..........", ".........."]);
+        rules.fill(&mut again, 1..9, 0..4, 11).unwrap();
+        assert_eq!(again, filled);
+
+        // tiles fixed outside the region are built on
+        let mut around = grid(&[".....", ".ab..", ".....", "....."]);
+        rules.fill(&mut around, 0..5, 2..4, 4).unwrap();
+        assert_eq!(around, grid(&[".....", ".ab..", ".cd..", ".ef.."]));
+    }
+
+    #[test]
+    fn test_declared_rules() {
+        let text = "# tops and walls\nt right t\nt down w\nw down w\nw right w\n\nw weight 3\n";
+        let rules: Rules<String> = text.parse().unwrap();
+        assert_eq!(rules.weight(&"w".to_string()), Some(3));
+        assert!(rules.allows(&"w".to_string(), Side::Up, &"t".to_string()));
+        assert_eq!(
+            "t across w"
+                .parse::<Rules<String>>()
+                .unwrap_err()
+                .to_string(),
+            "line 1: couldn't read rule 't across w'"
+        );
+
+        // a layer of a level filled from the rules: nothing goes above a top,
+        // and rows don't mix
+        let mut level: Level = "box 4 3\n====\nt s 0 0 16 16\nw s 16 0 16 16\n====\nt t t t\nw w w w\nw w w w\n====\nplayer 0 0\n"
+            .parse()
+            .unwrap();
+        fill_level(&mut level, 0, 0..4, 0..3, &rules, 1).unwrap();
+        let grid_of = |level: &Level| level.layers()[0].grid.clone();
+        let w = level.symbol_index("w").unwrap();
+        let filled = grid_of(&level);
+        assert!((0..4).all(|x| filled[(x, 0)] == filled[(0, 0)]));
+        assert!((0..4).all(|x| (1..3).all(|y| filled[(x, y)] == w)));
+        // here w can't have anything beside it, so a row of them can't fit
+        let mut rules: Rules<char> = Rules::new();
+        rules.allow('t', Side::Right, 't');
+        rules.allow('t', Side::Down, 'w');
+        let mut stuck = grid(&["tt", "tt", "tt"]);
+        assert_eq!(rules.fill(&mut stuck, 0..2, 1..3, 0), Err(Contradiction));
+        assert_eq!(stuck, grid(&["tt", "tt", "tt"]));
+    }
+}
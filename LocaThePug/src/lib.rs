@@ -0,0 +1,494 @@
+use assets_manager::AssetCache;
+use engine::campaign::CampaignError;
+use engine::ecs::{Animator, Body, Collider, Controller, Destination, Sprite};
+use engine::geom::*;
+use engine::level::{world_to_start, EntityType, Level, TileFlags};
+use engine::procgen::{Endless, JumpArc, ObstacleGenerator, ObstacleKind};
+use engine::reach::{Reach, ReachReport};
+use engine::{systems, AnimationState, Campaign, Entity, InputSource, Tags, World, TILE_SZ};
+use frenderer::{input::Key, sprites::Camera2D};
+
+pub const GRAV_ACC: f32 = 300.0;
+pub const WALK_ACC: f32 = 180.0;
+pub const MAX_SPEED: f32 = 90.0;
+pub const BRAKE_DAMP: f32 = 0.9;
+pub const JUMP_VEL: f32 = 140.0;
+pub const JUMP_TIME_MAX: f32 = 0.25;
+
+pub const W: usize = 16 * TILE_SZ;
+pub const H: usize = 12 * TILE_SZ;
+const SCREEN_FAST_MARGIN: f32 = 64.0;
+// Endless runs stream the level in chunks this many columns wide
+pub const ENDLESS_CHUNK: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RunStatus {
+    Running,
+    // hit a sign and got sent back to the start
+    Failed,
+    // made it to the column of signs at the end of the last level
+    Finished,
+}
+
+// Everything that changes while playing, with no renderer attached, so it can
+// be built from levels and stepped in tests and tools as well as by the game.
+pub struct Sim {
+    pub current_level: usize,
+    pub campaign: Campaign,
+    pub world: World,
+    pub player: Entity,
+    pub camera: Camera2D,
+    pub status: RunStatus,
+    touching_obstacle: bool,
+    end_x: f32,
+    endless: Option<EndlessRun>,
+}
+
+// An endless run's level streamer, and the level it started from so the run
+// can start over
+struct EndlessRun {
+    stream: Endless,
+    seed: u64,
+    original: Level,
+}
+
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AnimationKey {
+    Blank = 0,
+    PlayerRightIdle,
+    PlayerRightWalk,
+    PlayerRightJumpRise,
+    PlayerRightJumpFall,
+    PlayerRightAttack,
+    PlayerLeftIdle,
+    PlayerLeftWalk,
+    PlayerLeftJumpRise,
+    PlayerLeftJumpFall,
+    PlayerLeftAttack,
+    EnemyRightWalk,
+    EnemyLeftWalk,
+}
+
+// Adds the entity for one level start to the world.  Enemies aren't in the
+// game for now, so their starts don't spawn anything.
+fn spawn(world: &mut World, etype: &EntityType, pos: Vec2) -> Option<Entity> {
+    match etype {
+        EntityType::Player => {
+            let e = world.spawn(pos);
+            world.velocities.insert(e, Vec2 { x: 0.0, y: 0.0 });
+            world.colliders.insert(
+                e,
+                Collider {
+                    offset: Vec2 { x: 8.0, y: -12.0 },
+                    w: 16,
+                    h: 24,
+                },
+            );
+            world.bodies.insert(
+                e,
+                Body {
+                    gravity: 1.0,
+                    grounded: true,
+                    touching: TileFlags::OPEN,
+                },
+            );
+            world.sprites.insert(
+                e,
+                Sprite {
+                    anim: AnimationState::new(AnimationKey::PlayerRightIdle as usize),
+                    offset: Vec2 { x: 26.0, y: 6.0 },
+                    w: 36,
+                    h: 36,
+                },
+            );
+            world.animators.insert(
+                e,
+                Animator {
+                    idle: AnimationKey::PlayerRightIdle as usize,
+                    walk: AnimationKey::PlayerRightWalk as usize,
+                    jump_rise: AnimationKey::PlayerRightJumpRise as usize,
+                    jump_fall: AnimationKey::PlayerRightJumpFall as usize,
+                },
+            );
+            world.controllers.insert(
+                e,
+                Controller {
+                    run_acc: WALK_ACC,
+                    max_speed: MAX_SPEED,
+                    ..Controller::new(Key::ArrowUp, JUMP_VEL, JUMP_TIME_MAX)
+                },
+            );
+            world.tags.insert(e, Tags::PLAYER);
+            Some(e)
+        }
+        EntityType::Obstacle(to, x, y) => {
+            let e = world.spawn(pos);
+            world.colliders.insert(
+                e,
+                Collider {
+                    offset: Vec2 { x: -8.0, y: -8.0 },
+                    w: 16,
+                    h: 16,
+                },
+            );
+            world.destinations.insert(
+                e,
+                Destination {
+                    level: to.clone(),
+                    x: *x,
+                    y: *y,
+                },
+            );
+            world.tags.insert(e, Tags::OBSTACLE);
+            Some(e)
+        }
+        EntityType::Enemy | EntityType::Mud => None,
+    }
+}
+
+// How chunk n of an endless run is filled: signs that send you back to the
+// start, far apart at first and closer every couple of chunks
+fn endless_generator(level: &Level) -> impl Fn(u64) -> ObstacleGenerator {
+    let to = level.name().to_string();
+    let (to_x, to_y) = level
+        .starts()
+        .iter()
+        .find(|(t, _, _)| *t == EntityType::Player)
+        .map(|(_, pos, _)| world_to_start(*pos, level.height() as u16))
+        .expect("Start level doesn't put the player anywhere");
+    move |n| {
+        let step = (n / 2).min(4) as u16;
+        let sign = ObstacleKind::new(&to, to_x, to_y).with_tile("ws");
+        // the jump at this chunk's speed keeps the closest signs far enough
+        // apart to land and jump again
+        ObstacleGenerator::new(vec![sign])
+            .with_spacing(10 - step, 17 - 2 * step)
+            .with_jump(JumpArc::new(
+                JUMP_VEL,
+                GRAV_ACC,
+                endless_speed(n),
+                16.0,
+                24.0,
+            ))
+    }
+}
+
+// The player runs a little faster every chunk, up to 20% faster
+fn endless_speed(n: u64) -> f32 {
+    MAX_SPEED * (1.0 + 0.05 * n.min(4) as f32)
+}
+
+fn start_endless(mut level: Level, seed: u64) -> (Level, Endless) {
+    let generator = endless_generator(&level);
+    let stream = Endless::new(&mut level, ENDLESS_CHUNK, seed, generator);
+    (level, stream)
+}
+
+// Whether `level` can be run from its start to its last column of signs
+// without touching any of the others, using the game's own physics
+pub fn check_level(level: &Level) -> ReachReport {
+    let mut world = World::default();
+    let player = spawn(&mut world, &EntityType::Player, player_start(level)).unwrap();
+    for (etype, pos, _) in level.starts().iter() {
+        if *etype != EntityType::Player {
+            spawn(&mut world, etype, *pos);
+        }
+    }
+    let end_x = level
+        .starts()
+        .iter()
+        .filter(|(etype, _, _)| matches!(etype, EntityType::Obstacle(..)))
+        .map(|(_, pos, _)| pos.x)
+        .reduce(f32::max)
+        .unwrap_or(f32::INFINITY);
+    let bounds = Vec2 {
+        x: (level.width() * TILE_SZ) as f32,
+        y: H as f32,
+    };
+    Reach::new(GRAV_ACC, 1.0 / 60.0, bounds)
+        .with_goal_x(end_x)
+        .check(world, player, level)
+}
+
+fn player_start(level: &Level) -> Vec2 {
+    level
+        .starts()
+        .iter()
+        .find(|(t, _, _)| *t == EntityType::Player)
+        .map(|(_, ploc, _)| *ploc + Vec2 { x: 0.0, y: 200.0 })
+        .expect("Start level doesn't put the player anywhere")
+}
+
+impl Sim {
+    // Takes a loaded campaign, or just a list of levels to start at the first of
+    pub fn new(campaign: impl Into<Campaign>) -> Self {
+        let campaign = campaign.into();
+        let current_level = campaign.start();
+        let mut world = World::default();
+        let player_start = player_start(campaign.level(current_level));
+        let player = spawn(&mut world, &EntityType::Player, player_start).unwrap();
+        let mut sim = Sim {
+            current_level,
+            camera: Camera2D {
+                screen_pos: [0.0, 0.0],
+                screen_size: [W as f32, H as f32],
+            },
+            campaign,
+            world,
+            player,
+            status: RunStatus::Running,
+            touching_obstacle: false,
+            end_x: f32::INFINITY,
+            endless: None,
+        };
+        sim.enter_level(player_start);
+        sim
+    }
+    // An endless run on `level`: everything past its first chunk is replaced
+    // by signs generated from `seed`, and more keep coming as you run.  The
+    // same seed always gives the same run.
+    pub fn endless(level: Level, seed: u64) -> Self {
+        let (streamed, stream) = start_endless(level.clone(), seed);
+        let mut sim = Sim::new(vec![streamed]);
+        sim.endless = Some(EndlessRun {
+            stream,
+            seed,
+            original: level,
+        });
+        sim.end_x = f32::INFINITY;
+        sim
+    }
+    // Starts the run over from the first level with a fresh player
+    pub fn restart(&mut self) {
+        self.current_level = self.campaign.start();
+        if let Some(run) = self.endless.as_mut() {
+            let (level, stream) = start_endless(run.original.clone(), run.seed);
+            *self.campaign.level_mut(self.current_level) = level;
+            run.stream = stream;
+        }
+        let player_start = player_start(self.level());
+        self.world.clear();
+        self.player = spawn(&mut self.world, &EntityType::Player, player_start).unwrap();
+        self.camera.screen_pos = [0.0, 0.0];
+        self.status = RunStatus::Running;
+        self.enter_level(player_start);
+    }
+    pub fn level(&self) -> &Level {
+        self.campaign.level(self.current_level)
+    }
+    pub fn player_pos(&self) -> Vec2 {
+        self.world.positions.get(self.player).copied().unwrap()
+    }
+    // Where the player goes back to, even once an endless run has scrolled
+    // the level's start away
+    fn start_pos(&self) -> Vec2 {
+        match &self.endless {
+            Some(run) => player_start(&run.original),
+            None => player_start(self.level()),
+        }
+    }
+    // How far the player has run, counting the chunks an endless run has
+    // dropped
+    pub fn distance(&self) -> f32 {
+        let offset = self.endless.as_ref().map_or(0.0, |run| run.stream.offset());
+        offset + self.player_pos().x
+    }
+    pub fn player_rect(&self) -> Rect {
+        self.world.rect(self.player).unwrap()
+    }
+    // Positions of every obstacle in the current level
+    pub fn obstacles(&self) -> impl Iterator<Item = Vec2> + '_ {
+        self.world
+            .with_tag(Tags::OBSTACLE)
+            .filter_map(|e| self.world.positions.get(e).copied())
+    }
+    // Picks up edited level files, keeping the player where they are so the
+    // edit can be tried right away.  An endless run plays a level generated
+    // from the file it started with, whose positions don't line up with the
+    // file's any more, so it carries on with what it has.
+    pub fn reload(&mut self, cache: &AssetCache) -> Result<(), CampaignError> {
+        if self.endless.is_some() {
+            return Ok(());
+        }
+        if self.campaign.reload(cache)?.contains(&self.current_level) {
+            let pos = self.player_pos();
+            self.enter_level(pos);
+        }
+        Ok(())
+    }
+    pub fn enter_level(&mut self, player_pos: Vec2) {
+        // the player carries over between levels, everything else is replaced
+        let old: Vec<Entity> = self
+            .world
+            .entities()
+            .filter(|e| *e != self.player)
+            .collect();
+        for e in old {
+            self.world.despawn(e);
+        }
+        // we will probably enter at a obstacle
+        self.touching_obstacle = true;
+        *self.world.positions.get_mut(self.player).unwrap() = player_pos;
+        for (etype, pos, props) in self.campaign.level(self.current_level).starts().iter() {
+            if *etype != EntityType::Player {
+                if let Some(e) = spawn(&mut self.world, etype, *pos) {
+                    self.world.props.insert(e, props.clone());
+                }
+            }
+        }
+        // the last column of signs is the end of the run, unless it's endless
+        self.end_x = self
+            .obstacles()
+            .map(|pos| pos.x)
+            .reduce(f32::max)
+            .filter(|_| self.endless.is_none())
+            .unwrap_or(f32::INFINITY);
+    }
+    // Keeps an endless run going: speeds the player up as they get further,
+    // and when the level scrolls, moves everything back to match and spawns
+    // the new chunk's signs
+    fn stream_endless(&mut self) {
+        let Some(run) = self.endless.as_mut() else {
+            return;
+        };
+        let player_x = self.world.positions.get(self.player).unwrap().x;
+        // the streamed level has dropped its player start by now
+        let generator = endless_generator(&run.original);
+        let level = self.campaign.level_mut(self.current_level);
+        let scrolled = run.stream.update(level, player_x, generator);
+        let dx = scrolled.unwrap_or(0.0);
+        let speed = endless_speed(run.stream.chunk_at(player_x + dx));
+        self.world
+            .controllers
+            .get_mut(self.player)
+            .unwrap()
+            .max_speed = speed;
+        if scrolled.is_none() {
+            return;
+        }
+        self.world.translate(Vec2 { x: dx, y: 0.0 });
+        self.camera.screen_pos[0] += dx;
+        let behind: Vec<Entity> = self
+            .world
+            .entities()
+            .filter(|e| *e != self.player && self.world.positions.get(*e).unwrap().x < 0.0)
+            .collect();
+        for e in behind {
+            self.world.despawn(e);
+        }
+        let level = self.campaign.level(self.current_level);
+        let new_chunk = ((level.width() - ENDLESS_CHUNK) * TILE_SZ) as f32;
+        for (etype, pos, props) in level.starts().iter() {
+            if *etype != EntityType::Player && pos.x >= new_chunk {
+                if let Some(e) = spawn(&mut self.world, etype, *pos) {
+                    self.world.props.insert(e, props.clone());
+                }
+            }
+        }
+    }
+    pub fn simulate(&mut self, input: &impl InputSource, dt: f32) {
+        let lw = self.level().width();
+        let lh = self.level().height();
+
+        systems::controls(&mut self.world, input, dt);
+        systems::patrol(&mut self.world, dt);
+        systems::gravity(&mut self.world, GRAV_ACC, dt);
+        systems::movement(&mut self.world, self.campaign.level(self.current_level), dt);
+        systems::keep_in_bounds(&mut self.world, (lw * TILE_SZ) as f32, H as f32);
+        systems::tile_collision(&mut self.world, self.campaign.level(self.current_level));
+        systems::animation(&mut self.world, dt);
+        self.campaign.level_mut(self.current_level).tick(dt);
+        self.stream_endless();
+        // after streaming, which can move everything back a chunk
+        self.world.update_broadphase();
+
+        // Obstacle collision and response
+        let triggers = self.world.contacts(Tags::PLAYER, Tags::OBSTACLE);
+        if triggers.is_empty() {
+            self.touching_obstacle = false;
+        }
+        // enter obstacle if player has moved, wasn't previously touching obstacle
+        if let Some(&(_, obstacle)) = triggers.first() {
+            if !self.touching_obstacle {
+                self.touching_obstacle = true;
+                self.hit_obstacle(obstacle);
+            }
+        }
+        // Goal and hazard tiles end the level like the signs do
+        let touching = self.world.bodies.get(self.player).unwrap().touching;
+        if touching.goal {
+            self.finish_level();
+        } else if touching.hazard {
+            self.status = RunStatus::Failed;
+            *self.world.positions.get_mut(self.player).unwrap() = self.start_pos();
+        }
+
+        let player_pos = self.player_pos();
+        while player_pos.x
+            > self.camera.screen_pos[0] + self.camera.screen_size[0] - SCREEN_FAST_MARGIN
+        {
+            self.camera.screen_pos[0] += 1.0;
+        }
+        while player_pos.x < self.camera.screen_pos[0] + SCREEN_FAST_MARGIN {
+            self.camera.screen_pos[0] -= 1.0;
+        }
+        while player_pos.y
+            > self.camera.screen_pos[1] + self.camera.screen_size[1] - SCREEN_FAST_MARGIN
+        {
+            self.camera.screen_pos[1] += 1.0;
+        }
+        while player_pos.y < self.camera.screen_pos[1] + SCREEN_FAST_MARGIN {
+            self.camera.screen_pos[1] -= 1.0;
+        }
+        self.camera.screen_pos[0] =
+            self.camera.screen_pos[0].clamp(0.0, (lw * TILE_SZ).max(W) as f32 - W as f32);
+        self.camera.screen_pos[1] =
+            self.camera.screen_pos[1].clamp(0.0, (lh * TILE_SZ).max(H) as f32 - H as f32);
+    }
+    // Goes on to the campaign's next level, or finishes the run after the last
+    fn finish_level(&mut self) {
+        match self.campaign.next(self.current_level) {
+            Some(next) => {
+                self.current_level = next;
+                self.enter_level(player_start(self.level()));
+            }
+            None => self.status = RunStatus::Finished,
+        }
+    }
+    fn hit_obstacle(&mut self, obstacle: Entity) {
+        let obstacle_pos = self.world.positions.get(obstacle).copied().unwrap();
+        if obstacle_pos.x >= self.end_x && self.campaign.next(self.current_level).is_some() {
+            self.finish_level();
+            return;
+        }
+        self.status = if obstacle_pos.x >= self.end_x {
+            RunStatus::Finished
+        } else {
+            RunStatus::Failed
+        };
+        let Destination { level, x, y } = self.world.destinations.get(obstacle).cloned().unwrap();
+        // loading and reloading a campaign checks where its obstacles go, so
+        // only a campaign built by hand can get here with a missing level
+        let dest = self.campaign.index_of(&level);
+        debug_assert!(
+            dest.is_some(),
+            "obstacle to {level}, which isn't in the campaign"
+        );
+        let Some(dest) = dest else {
+            return;
+        };
+        let offset = Vec2 {
+            x: TILE_SZ as f32 / 2.0,
+            y: -12.0 + TILE_SZ as f32 / 2.0,
+        };
+        if dest == self.current_level {
+            *self.world.positions.get_mut(self.player).unwrap() =
+                self.level().grid_to_world((x as usize, y as usize)) + offset;
+        } else {
+            self.current_level = dest;
+            self.enter_level(self.level().grid_to_world((x as usize, y as usize)) + offset);
+        }
+    }
+}
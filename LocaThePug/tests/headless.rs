@@ -0,0 +1,182 @@
+use engine::level::{start_to_world, world_to_start, EntityType, Level, Properties};
+use engine::{Campaign, HeldKeys};
+use frenderer::input::Key;
+use loca_the_pug::{check_level, RunStatus, Sim};
+
+const DT: f32 = 1.0 / 60.0;
+// a minute of play is plenty for level1
+const MAX_FRAMES: usize = 60 * 60;
+
+fn level1() -> Sim {
+    Sim::new(vec![include_str!("../content/level1.txt")
+        .parse::<Level>()
+        .unwrap()])
+}
+
+// Steps the sim until the run ends, letting `press_jump` decide each frame
+fn run(sim: &mut Sim, press_jump: impl Fn(&Sim) -> bool) -> usize {
+    let mut keys = HeldKeys::default();
+    for frame in 0..MAX_FRAMES {
+        if sim.status != RunStatus::Running {
+            return frame;
+        }
+        keys.set(Key::ArrowUp, press_jump(sim));
+        sim.simulate(&keys, DT);
+    }
+    MAX_FRAMES
+}
+
+#[test]
+fn never_jumping_fails_at_the_first_sign() {
+    let mut sim = level1();
+    let frames = run(&mut sim, |_| false);
+    assert_eq!(sim.status, RunStatus::Failed);
+    assert!(frames < MAX_FRAMES);
+    // and we get put back at the start of the level
+    assert!(sim.player_pos().x < 3.0 * 16.0);
+}
+
+// Jumps when a sign is just ahead
+fn jump_at_signs(sim: &Sim) -> bool {
+    let prect = sim.player_rect();
+    let front = prect.x + prect.w as f32;
+    sim.obstacles().any(|pos| {
+        let gap = pos.x - 8.0 - front;
+        (-4.0..28.0).contains(&gap)
+    })
+}
+
+#[test]
+fn jumping_at_each_sign_reaches_the_end() {
+    let mut sim = level1();
+    run(&mut sim, jump_at_signs);
+    assert_eq!(sim.status, RunStatus::Finished);
+}
+
+// level1 followed by a copy of it called level2
+fn two_levels() -> Sim {
+    let manifest = "level1 next=level2\nlevel2\n";
+    let campaign = Campaign::parse("campaign.txt", manifest, |name| {
+        include_str!("../content/level1.txt")
+            .replace("level1", name)
+            .parse()
+    })
+    .unwrap();
+    Sim::new(campaign)
+}
+
+#[test]
+fn finishing_a_level_goes_on_to_the_next() {
+    let mut sim = two_levels();
+    run(&mut sim, jump_at_signs);
+    assert_eq!(sim.current_level, 1);
+    assert_eq!(sim.status, RunStatus::Finished);
+    // only jumping through level1 gets us to level2, but no further
+    let mut sim = two_levels();
+    run(&mut sim, |sim| sim.current_level == 0 && jump_at_signs(sim));
+    assert_eq!(sim.current_level, 1);
+    assert_eq!(sim.status, RunStatus::Failed);
+}
+
+#[test]
+fn goal_and_hazard_tiles_end_the_run() {
+    // paint the top of the ground as a goal, then as a hazard
+    for (flags, status) in [
+        ("s,goal", RunStatus::Finished),
+        ("s,hazard", RunStatus::Failed),
+    ] {
+        let text =
+            include_str!("../content/level1.txt").replace("gdfc s ", &format!("gdfc {flags} "));
+        let mut sim = Sim::new(vec![text.parse::<Level>().unwrap()]);
+        let frames = run(&mut sim, |_| false);
+        assert_eq!(sim.status, status);
+        // the player ends the run as soon as they land, before the first sign
+        assert!(sim.obstacles().all(|pos| sim.player_pos().x < pos.x));
+        assert!(frames < 60 * 3);
+    }
+}
+
+#[test]
+fn the_level_checker_finds_signs_nobody_can_jump() {
+    let text = include_str!("../content/level1.txt");
+    let report = check_level(&text.parse().unwrap());
+    assert!(report.reachable && !report.gave_up, "{report:?}");
+    assert!(report.unavoidable.is_empty());
+
+    // stack the sign in column 46 three high
+    let mut level: Level = text.parse().unwrap();
+    for y in [8, 7] {
+        level.add_start(
+            EntityType::Obstacle("level1".to_string(), 2, 8),
+            start_to_world(46, y, level.height() as u16),
+            Properties::default(),
+        );
+    }
+    let report = check_level(&level);
+    assert!(!report.reachable, "{report:?}");
+    assert!(!report.unavoidable.is_empty());
+    for pos in report.unavoidable.iter() {
+        assert_eq!(world_to_start(*pos, level.height() as u16).0, 46);
+    }
+    // the player can run right up to it but no further
+    assert!(report.furthest.x < 46.0 * 16.0 && report.furthest.x > 40.0 * 16.0);
+
+    // and a wall that's too tall to jump has no sign to blame
+    let mut wall: Level = text.parse().unwrap();
+    let ground = wall.symbol_index("gdfc").unwrap();
+    for y in 4..10 {
+        wall.set_tile(0, 25, y, ground);
+    }
+    let report = check_level(&wall);
+    assert!(
+        !report.reachable && report.unavoidable.is_empty(),
+        "{report:?}"
+    );
+    assert!(report.furthest.x < 25.0 * 16.0);
+}
+
+fn endless(seed: u64) -> Sim {
+    Sim::endless(
+        include_str!("../content/level1.txt")
+            .parse::<Level>()
+            .unwrap(),
+        seed,
+    )
+}
+
+#[test]
+fn endless_runs_stream_new_chunks() {
+    let mut sim = endless(3);
+    let width = sim.level().width();
+    let mut keys = HeldKeys::default();
+    let mut furthest: f32 = 0.0;
+    for _ in 0..MAX_FRAMES {
+        keys.set(Key::ArrowUp, jump_at_signs(&sim));
+        sim.simulate(&keys, DT);
+        assert_eq!(sim.status, RunStatus::Running);
+        // the level stays the same size and positions stay inside it
+        assert_eq!(sim.level().width(), width);
+        assert!(sim.player_pos().x < (width * 16) as f32);
+        furthest = furthest.max(sim.distance());
+    }
+    // a minute of running goes much further than level1 is long
+    assert!(furthest > 3.0 * (width * 16) as f32, "{furthest}");
+}
+
+#[test]
+fn endless_runs_replay_from_their_seed() {
+    let signs = |sim: &Sim| {
+        let mut xs: Vec<f32> = sim.obstacles().map(|pos| pos.x).collect();
+        xs.sort_by(f32::total_cmp);
+        xs
+    };
+    assert_eq!(signs(&endless(3)), signs(&endless(3)));
+    assert_ne!(signs(&endless(3)), signs(&endless(4)));
+    // and failing and starting over plays the same run again
+    let mut sim = endless(3);
+    let first = signs(&sim);
+    run(&mut sim, |_| false);
+    assert_eq!(sim.status, RunStatus::Failed);
+    sim.restart();
+    assert_eq!(signs(&sim), first);
+}